@@ -0,0 +1,328 @@
+//! Timing configuration
+//!
+//! Settings of a manager that depend on each other. They are frequency, scheduling,
+//! step size, tick max, zero crossing offset, mains timeout, detection timeout and
+//! glitch filter.
+//!
+//! `TimingConfigBuilder` checks them, so a `TimingConfig` always works.
+use crate::error::*;
+use crate::frequency::Frequency;
+use crate::glitch::{GlitchFilter, DEFAULT_EDGE_WINDOW, DEFAULT_MIN_PULSE_WIDTH};
+use crate::pll::{ZeroCrossingOffset, TICKS_PER_HALF_PERIOD};
+use crate::schedule::Scheduling;
+use crate::watchdog::DEFAULT_MAINS_TIMEOUT;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// Default time to detect frequency, then to calibrate offset, at startup.
+pub const DEFAULT_DETECTION_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximal tick value by default. Cannot work 100% because of the zero crossing
+/// detection timer on the same core.
+pub const DEFAULT_TICK_MAX: u8 = 95;
+// Shortest half period (60Hz) in µs, to check offset when frequency is auto
+const SHORTEST_HALF_PERIOD: u32 = 8_333;
+// Longest half period (50Hz) in µs, to check mains timeout when frequency is auto
+const LONGEST_HALF_PERIOD: u32 = 10_000;
+// PLL predicts two missed zero crossings, mains timeout must be longer
+const MIN_MAINS_TIMEOUT_HALF_PERIODS: u32 = 3;
+// Edge window must let PLL jitter through (percent of half period)
+const MAX_EDGE_WINDOW: u8 = 90;
+
+/// Checked timing settings of a manager. Use `TimingConfig::builder()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingConfig {
+    frequency: Frequency,
+    scheduling: Scheduling,
+    step_size: u8,
+    tick_max: u8,
+    zero_crossing_offset: ZeroCrossingOffset,
+    mains_timeout: Duration,
+    detection_timeout: Duration,
+    edge_window: u8,
+    min_pulse_width: u32,
+}
+
+impl TimingConfig {
+    /// Create a builder with default values.
+    pub fn builder() -> TimingConfigBuilder {
+        TimingConfigBuilder::new()
+    }
+
+    /// Frequency of network.
+    pub fn frequency(&self) -> &Frequency {
+        &self.frequency
+    }
+
+    /// How ISR timer is used.
+    pub fn scheduling(&self) -> Scheduling {
+        self.scheduling
+    }
+
+    /// Value add to tick at each timer call with `Scheduling::Polling`.
+    pub fn step_size(&self) -> u8 {
+        self.step_size
+    }
+
+    /// After this tick, all devices are reset.
+    pub fn tick_max(&self) -> u8 {
+        self.tick_max
+    }
+
+    /// Delay between true zero crossing and zero crossing edge.
+    pub fn zero_crossing_offset(&self) -> &ZeroCrossingOffset {
+        &self.zero_crossing_offset
+    }
+
+    /// Time without zero crossing before mains is lost.
+    pub fn mains_timeout(&self) -> Duration {
+        self.mains_timeout
+    }
+
+    /// Time to detect frequency, then to calibrate offset, when they are `Auto`.
+    pub fn detection_timeout(&self) -> Duration {
+        self.detection_timeout
+    }
+
+    /// Part of half period (in percent) before a zero crossing edge is accepted.
+    pub fn edge_window(&self) -> u8 {
+        self.edge_window
+    }
+
+    /// Minimal width in µs of zero crossing pulse.
+    pub fn min_pulse_width(&self) -> u32 {
+        self.min_pulse_width
+    }
+
+    /// Glitch filter for zero crossing edges. `None` if frequency is not detected yet.
+    pub fn glitch_filter(&self) -> Option<GlitchFilter> {
+        self.frequency.half_period().map(|half_period| {
+            GlitchFilter::new(half_period)
+                .with_window(self.edge_window)
+                .with_min_pulse_width(self.min_pulse_width)
+        })
+    }
+
+    /// Glitch filter to detect frequency. Edges are checked against shortest half period
+    /// (60Hz).
+    pub fn detection_glitch_filter(&self) -> GlitchFilter {
+        GlitchFilter::new(SHORTEST_HALF_PERIOD)
+            .with_window(self.edge_window)
+            .with_min_pulse_width(self.min_pulse_width)
+    }
+
+    /// Period of timer with `Scheduling::Polling`. `None` if frequency is not detected
+    /// yet.
+    pub fn timer_period(&self) -> Option<Duration> {
+        self.frequency
+            .step_duration()
+            .map(|d| Duration::from_micros(d as u64 * self.step_size as u64))
+    }
+
+    /// Replace `Frequency::Auto` by detected frequency.
+    pub fn with_detected_frequency(mut self, frequency: Frequency) -> Self {
+        if self.frequency == Frequency::Auto {
+            self.frequency = frequency;
+        }
+
+        self
+    }
+}
+
+/// Builder of `TimingConfig`.
+#[derive(Debug, Clone)]
+pub struct TimingConfigBuilder {
+    config: TimingConfig,
+}
+
+impl Default for TimingConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimingConfigBuilder {
+    /// Create a builder. By default, frequency is detected in 1s, timer is one-shot,
+    /// step size is 1, tick max is 95, there is no offset, mains timeout is 100ms, edges
+    /// are accepted after 75% of half period and pulses must be wider than 100µs.
+    pub fn new() -> Self {
+        Self {
+            config: TimingConfig {
+                frequency: Frequency::Auto,
+                scheduling: Scheduling::default(),
+                step_size: 1,
+                tick_max: DEFAULT_TICK_MAX,
+                zero_crossing_offset: ZeroCrossingOffset::Fixed(0),
+                mains_timeout: DEFAULT_MAINS_TIMEOUT,
+                detection_timeout: DEFAULT_DETECTION_TIMEOUT,
+                edge_window: DEFAULT_EDGE_WINDOW,
+                min_pulse_width: DEFAULT_MIN_PULSE_WIDTH,
+            },
+        }
+    }
+
+    /// Frequency of network (Europe = 50Hz). With `Frequency::Auto`, frequency is
+    /// measured on zero crossing pin when manager is created.
+    pub fn frequency(mut self, frequency: Frequency) -> Self {
+        self.config.frequency = frequency;
+        self
+    }
+
+    /// How ISR timer is used. See `Scheduling`.
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.config.scheduling = scheduling;
+        self
+    }
+
+    /// Step of manage power with `Scheduling::Polling`. In 50Hz, by default, power is
+    /// managed every 0.1ms. But you can multiy by step_size.
+    /// That mean is step_size = 10, power management is every 1ms and
+    /// power tick is also multiply by 10 (power step wil by 0, 10, 20, 30...)
+    pub fn step_size(mut self, step_size: u8) -> Self {
+        self.config.step_size = step_size;
+        self
+    }
+
+    /// Tick max of power management in percent. Must be a multiple of step size.
+    /// By default, you cannot set power more than 95%.
+    pub fn tick_max(mut self, tick_max: u8) -> Self {
+        self.config.tick_max = tick_max;
+        self
+    }
+
+    /// Delay between true zero crossing and falling edge of zero crossing pin.
+    /// With `ZeroCrossingOffset::Auto`, delay is measured when manager is created.
+    pub fn zero_crossing_offset(mut self, offset: ZeroCrossingOffset) -> Self {
+        self.config.zero_crossing_offset = offset;
+        self
+    }
+
+    /// Time without zero crossing before all devices are turned off. Must be longer than
+    /// 3 half periods.
+    pub fn mains_timeout(mut self, timeout: Duration) -> Self {
+        self.config.mains_timeout = timeout;
+        self
+    }
+
+    /// Time to detect frequency, then to calibrate offset, when they are `Auto`. Manager
+    /// creation fails with `RbdDimmerErrorKind::MainsLost` after it. Cannot be 0.
+    pub fn detection_timeout(mut self, timeout: Duration) -> Self {
+        self.config.detection_timeout = timeout;
+        self
+    }
+
+    /// Zero crossing edges that arrive before `window` percent of half period are
+    /// ignored. 0 disables check, maximum is 90.
+    pub fn edge_window(mut self, window: u8) -> Self {
+        self.config.edge_window = window;
+        self
+    }
+
+    /// Zero crossing pulses shorter than `width` µs are ignored. 0 disables check.
+    pub fn min_pulse_width(mut self, width: u32) -> Self {
+        self.config.min_pulse_width = width;
+        self
+    }
+
+    /// Check settings.
+    pub fn build(self) -> Result<TimingConfig, RbdDimmerError> {
+        let config = self.config;
+
+        if config.step_size == 0 {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidStepSize,
+                String::from("Step size cannot be 0"),
+            ));
+        }
+
+        if config.tick_max == 0 || config.tick_max > TICKS_PER_HALF_PERIOD {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidTickMax,
+                format!(
+                    "Tick max must be between 1 and {}, not {}",
+                    TICKS_PER_HALF_PERIOD, config.tick_max
+                ),
+            ));
+        }
+
+        if !config.tick_max.is_multiple_of(config.step_size) {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidTickMax,
+                format!(
+                    "Tick max {} is not a multiple of step size {}",
+                    config.tick_max, config.step_size
+                ),
+            ));
+        }
+
+        if let ZeroCrossingOffset::Fixed(offset) = config.zero_crossing_offset {
+            let half_period = config
+                .frequency
+                .half_period()
+                .unwrap_or(SHORTEST_HALF_PERIOD);
+
+            if offset.unsigned_abs() >= half_period {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::InvalidZeroCrossingOffset,
+                    format!(
+                        "Offset {}µs is longer than half period {}µs",
+                        offset, half_period
+                    ),
+                ));
+            }
+        }
+
+        let min_timeout = Duration::from_micros(
+            (config
+                .frequency
+                .half_period()
+                .unwrap_or(LONGEST_HALF_PERIOD)
+                * MIN_MAINS_TIMEOUT_HALF_PERIODS) as u64,
+        );
+
+        if config.mains_timeout <= min_timeout {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidMainsTimeout,
+                format!(
+                    "Mains timeout {:?} must be longer than {:?}",
+                    config.mains_timeout, min_timeout
+                ),
+            ));
+        }
+
+        if config.detection_timeout.is_zero() {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidDetectionTimeout,
+                String::from("Detection timeout cannot be 0"),
+            ));
+        }
+
+        if config.edge_window > MAX_EDGE_WINDOW {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidGlitchFilter,
+                format!(
+                    "Edge window must be between 0 and {}%, not {}%",
+                    MAX_EDGE_WINDOW, config.edge_window
+                ),
+            ));
+        }
+
+        let half_period = config
+            .frequency
+            .half_period()
+            .unwrap_or(SHORTEST_HALF_PERIOD);
+
+        if config.min_pulse_width >= half_period {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidGlitchFilter,
+                format!(
+                    "Pulse width {}µs is longer than half period {}µs",
+                    config.min_pulse_width, half_period
+                ),
+            ));
+        }
+
+        Ok(config)
+    }
+}
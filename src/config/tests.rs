@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use crate::config::*;
+use crate::error::RbdDimmerErrorKind;
+use crate::frequency::Frequency;
+use crate::pll::ZeroCrossingOffset;
+use crate::schedule::Scheduling;
+
+#[test]
+fn test_default() {
+    let config = TimingConfig::builder().build().unwrap();
+
+    assert_eq!(config.frequency(), &Frequency::Auto);
+    assert_eq!(config.scheduling(), Scheduling::OneShot);
+    assert_eq!(config.step_size(), 1);
+    assert_eq!(config.tick_max(), DEFAULT_TICK_MAX);
+    assert_eq!(config.zero_crossing_offset(), &ZeroCrossingOffset::Fixed(0));
+    assert_eq!(config.mains_timeout(), Duration::from_millis(100));
+    assert_eq!(config.detection_timeout(), Duration::from_secs(1));
+    assert_eq!(config.edge_window(), 75);
+    assert_eq!(config.min_pulse_width(), 100);
+    // Frequency is not detected
+    assert!(config.glitch_filter().is_none());
+    assert_eq!(config.timer_period(), None);
+}
+
+#[test]
+fn test_timer_period() {
+    let config = TimingConfig::builder()
+        .frequency(Frequency::F60HZ)
+        .scheduling(Scheduling::Polling)
+        .step_size(5)
+        .tick_max(90)
+        .build()
+        .unwrap();
+
+    assert_eq!(config.timer_period(), Some(Duration::from_micros(415)));
+
+    let config = TimingConfig::builder()
+        .build()
+        .unwrap()
+        .with_detected_frequency(Frequency::F50HZ);
+
+    assert_eq!(config.frequency(), &Frequency::F50HZ);
+    assert_eq!(config.timer_period(), Some(Duration::from_micros(100)));
+
+    // Frequency is not auto
+    let config = config.with_detected_frequency(Frequency::F60HZ);
+
+    assert_eq!(config.frequency(), &Frequency::F50HZ);
+}
+
+#[test]
+fn test_invalid_step_size() {
+    let error = TimingConfig::builder().step_size(0).build().unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidStepSize);
+}
+
+#[test]
+fn test_invalid_tick_max() {
+    let error = TimingConfig::builder().tick_max(0).build().unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidTickMax);
+
+    let error = TimingConfig::builder().tick_max(101).build().unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidTickMax);
+
+    // Tick jumps over tick max
+    let error = TimingConfig::builder()
+        .step_size(10)
+        .tick_max(95)
+        .build()
+        .unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidTickMax);
+}
+
+#[test]
+fn test_invalid_offset() {
+    let error = TimingConfig::builder()
+        .frequency(Frequency::F50HZ)
+        .zero_crossing_offset(ZeroCrossingOffset::Fixed(-10_000))
+        .build()
+        .unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidZeroCrossingOffset);
+
+    // 60Hz is possible
+    let error = TimingConfig::builder()
+        .zero_crossing_offset(ZeroCrossingOffset::Fixed(9_000))
+        .build()
+        .unwrap_err();
+
+    assert_eq!(error.kind, RbdDimmerErrorKind::InvalidZeroCrossingOffset);
+
+    assert!(TimingConfig::builder()
+        .frequency(Frequency::F50HZ)
+        .zero_crossing_offset(ZeroCrossingOffset::Fixed(9_000))
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn test_invalid_mains_timeout() {
+    // 3 half periods in 50Hz
+    match TimingConfig::builder()
+        .mains_timeout(Duration::from_millis(30))
+        .build()
+    {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidMainsTimeout),
+    }
+
+    let config = TimingConfig::builder()
+        .frequency(Frequency::F60HZ)
+        .mains_timeout(Duration::from_millis(30))
+        .build()
+        .unwrap();
+
+    assert_eq!(config.mains_timeout(), Duration::from_millis(30));
+}
+
+#[test]
+fn test_invalid_detection_timeout() {
+    match TimingConfig::builder()
+        .detection_timeout(Duration::ZERO)
+        .build()
+    {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidDetectionTimeout),
+    }
+
+    let config = TimingConfig::builder()
+        .detection_timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+
+    assert_eq!(config.detection_timeout(), Duration::from_millis(500));
+}
+
+#[test]
+fn test_detection_glitch_filter() {
+    let mut filter = TimingConfig::builder()
+        .edge_window(50)
+        .build()
+        .unwrap()
+        .detection_glitch_filter();
+
+    // Checked against 60Hz until frequency is known
+    assert_eq!(filter.min_interval(), 4_166);
+    assert!(filter.edge(10_000, Some(500)));
+    assert!(!filter.edge(12_000, Some(500)));
+    assert!(filter.edge(20_000, Some(500)));
+}
+
+#[test]
+fn test_glitch_filter() {
+    let config = TimingConfig::builder()
+        .frequency(Frequency::F50HZ)
+        .edge_window(50)
+        .min_pulse_width(0)
+        .build()
+        .unwrap();
+
+    let mut filter = config.glitch_filter().unwrap();
+
+    assert_eq!(filter.min_interval(), 5_000);
+    assert!(filter.edge(0, Some(0)));
+
+    match TimingConfig::builder().edge_window(91).build() {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidGlitchFilter),
+    }
+
+    match TimingConfig::builder()
+        .frequency(Frequency::F50HZ)
+        .min_pulse_width(10_000)
+        .build()
+    {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidGlitchFilter),
+    }
+}
@@ -0,0 +1,254 @@
+//! Power curves
+//!
+//! A dimmer device turns on triac during a percent of time of half sinusoidal. But
+//! power of a resistive load is not linear with this time: voltage is low near zero
+//! crossing and high in the middle of half sinusoidal.
+//!
+//! With a firing angle α (0 to π), the RMS power of a resistive load is:
+//!
+//! P(α) / Pmax = 1 - α/π + sin(2α)/(2π)
+//!
+//! This module convert percent of RMS power to percent of time and the opposite.
+//! It doesn't depend on ESP and can be used by all managers.
+//!
+//! Real loads (lamps, heaters) don't follow the theoretical curve (dead zone,
+//! saturation). A `CalibrationTable` can be built from measurements of the load.
+use crate::error::*;
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod tests;
+
+// Number of bisection iterations. Enough for precision better than 0.01%.
+const BISECTION_ITERATIONS: u8 = 24;
+// Number of entries in calibration table (0% to 100%).
+const TABLE_SIZE: usize = 101;
+
+/// How power requested is understood by dimmer device.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum PowerMode {
+    /// Power is percent of time of half sinusoidal (like official library).
+    #[default]
+    Time,
+    /// Power is percent of RMS power delivered to a resistive load.
+    Rms,
+    /// Power is converted by a calibration table of the load.
+    Calibrated(CalibrationTable),
+}
+
+impl PowerMode {
+    /// Percent of time of half sinusoidal for a requested power.
+    pub fn conduction(&self, power: u8) -> u8 {
+        match self {
+            PowerMode::Time => power.min(100),
+            PowerMode::Rms => rms_to_conduction(power),
+            PowerMode::Calibrated(table) => table.conduction(power),
+        }
+    }
+
+    /// Per-mille of time of half sinusoidal for a requested power in per-mille.
+    pub fn conduction_permille(&self, power: u16) -> u16 {
+        match self {
+            PowerMode::Time => power.min(1000),
+            PowerMode::Rms => rms_to_conduction_permille(power),
+            PowerMode::Calibrated(table) => table.conduction_permille(power),
+        }
+    }
+}
+
+/// Lookup table of a load: requested power (0% to 100%) to percent of time of half
+/// sinusoidal. Firing tick is 100 - percent of time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationTable {
+    conduction: [u8; TABLE_SIZE],
+}
+
+impl CalibrationTable {
+    /// Create table from 101 values of percent of time.
+    pub fn new(conduction: [u8; TABLE_SIZE]) -> Self {
+        Self {
+            conduction: conduction.map(|c| c.min(100)),
+        }
+    }
+
+    /// Create table from a list of percent of time. Table must have 101 values.
+    pub fn from_slice(conduction: &[u8]) -> Result<Self, RbdDimmerError> {
+        match <[u8; TABLE_SIZE]>::try_from(conduction) {
+            Ok(c) => Ok(Self::new(c)),
+            Err(_) => Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidCalibration,
+                format!(
+                    "Calibration table must have {} values, not {}",
+                    TABLE_SIZE,
+                    conduction.len()
+                ),
+            )),
+        }
+    }
+
+    /// Build table from measurements of power (in W) for percent of time of half
+    /// sinusoidal, like table in `doc/HOW-IT-WORKS.md`. `full_power` is power of load
+    /// at 100%.
+    ///
+    /// Measurements are interpolated (0% gives 0W and 100% gives `full_power` if not
+    /// measured), then curve is inverted.
+    pub fn from_measurements(
+        measures: &[(u8, f32)],
+        full_power: f32,
+    ) -> Result<Self, RbdDimmerError> {
+        if !full_power.is_finite() || full_power <= 0.0 {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidCalibration,
+                format!("Full power must be positive: {}", full_power),
+            ));
+        }
+
+        let mut points: Vec<(u8, f32)> = vec![];
+
+        for &(percent, watts) in measures {
+            if percent > 100 || !watts.is_finite() || watts < 0.0 {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::InvalidCalibration,
+                    format!("Invalid measure: {}% = {}W", percent, watts),
+                ));
+            }
+
+            points.push((percent, watts));
+        }
+
+        if points.is_empty() {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidCalibration,
+                String::from("No measure"),
+            ));
+        }
+
+        points.sort_by_key(|p| p.0);
+        points.dedup_by_key(|p| p.0);
+
+        if points[0].0 > 0 {
+            points.insert(0, (0, 0.0));
+        }
+
+        if points[points.len() - 1].0 < 100 {
+            points.push((100, full_power));
+        }
+
+        // Power for each percent of time. Keep curve monotonic to invert it.
+        let mut watts = [0.0_f32; TABLE_SIZE];
+        let mut max = 0.0_f32;
+
+        for (t, w) in watts.iter_mut().enumerate() {
+            max = max.max(interpolate(&points, t as u8));
+            *w = max;
+        }
+
+        let mut conduction = [0_u8; TABLE_SIZE];
+
+        for (p, c) in conduction.iter_mut().enumerate().skip(1) {
+            let target = full_power * p as f32 / 100.0;
+
+            *c = match watts.iter().position(|&w| w >= target) {
+                // Take nearest percent of time
+                Some(t) if t > 0 && target - watts[t - 1] < watts[t] - target => (t - 1) as u8,
+                Some(t) => t as u8,
+                None => 100,
+            };
+        }
+
+        Ok(Self { conduction })
+    }
+
+    /// Percent of time of half sinusoidal for a requested power.
+    pub fn conduction(&self, power: u8) -> u8 {
+        self.conduction[power.min(100) as usize]
+    }
+
+    /// Per-mille of time of half sinusoidal for a requested power in per-mille. Table is
+    /// interpolated between percents.
+    pub fn conduction_permille(&self, power: u16) -> u16 {
+        let power = power.min(1000);
+        let index = (power / 10) as usize;
+        let low = self.conduction[index] as u16 * 10;
+
+        match self.conduction.get(index + 1) {
+            Some(&high) => {
+                let high = high as u16 * 10;
+                let fraction = power % 10;
+
+                // Table is monotonic, but don't trust it
+                if high >= low {
+                    low + (high - low) * fraction / 10
+                } else {
+                    low - (low - high) * fraction / 10
+                }
+            }
+            None => low,
+        }
+    }
+}
+
+// Linear interpolation of sorted points.
+fn interpolate(points: &[(u8, f32)], x: u8) -> f32 {
+    for pair in points.windows(2) {
+        let (x0, y0) = pair[0];
+        let (x1, y1) = pair[1];
+
+        if x <= x1 {
+            return y0 + (y1 - y0) * (x - x0) as f32 / (x1 - x0) as f32;
+        }
+    }
+
+    points[points.len() - 1].1
+}
+
+/// Fraction (0.0 to 1.0) of RMS power of a resistive load for a firing angle in radian.
+pub fn rms_fraction(firing_angle: f32) -> f32 {
+    let a = firing_angle.clamp(0.0, PI);
+
+    (1.0 - a / PI + (2.0 * a).sin() / (2.0 * PI)).clamp(0.0, 1.0)
+}
+
+/// Firing angle in radian to deliver a fraction (0.0 to 1.0) of RMS power.
+pub fn firing_angle(fraction: f32) -> f32 {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let mut low = 0.0;
+    let mut high = PI;
+
+    // rms_fraction() decrease when firing angle increase
+    for _ in 0..BISECTION_ITERATIONS {
+        let middle = (low + high) / 2.0;
+
+        if rms_fraction(middle) > fraction {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+/// Percent of time of half sinusoidal to deliver a percent of RMS power.
+pub fn rms_to_conduction(power: u8) -> u8 {
+    let power = power.min(100);
+    let angle = firing_angle(power as f32 / 100.0);
+
+    ((1.0 - angle / PI) * 100.0).round() as u8
+}
+
+/// Per-mille of time of half sinusoidal to deliver a per-mille of RMS power.
+pub fn rms_to_conduction_permille(power: u16) -> u16 {
+    let power = power.min(1000);
+    let angle = firing_angle(power as f32 / 1000.0);
+
+    ((1.0 - angle / PI) * 1000.0).round() as u16
+}
+
+/// Percent of RMS power delivered with a percent of time of half sinusoidal.
+pub fn conduction_to_rms(conduction: u8) -> u8 {
+    let conduction = conduction.min(100);
+    let angle = (1.0 - conduction as f32 / 100.0) * PI;
+
+    (rms_fraction(angle) * 100.0).round() as u8
+}
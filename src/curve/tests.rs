@@ -0,0 +1,181 @@
+use std::f32::consts::PI;
+
+use crate::curve::*;
+use crate::error::RbdDimmerErrorKind;
+
+#[test]
+fn test_rms_fraction() {
+    assert_eq!(rms_fraction(0.0), 1.0);
+    assert!(rms_fraction(PI).abs() < 0.0001);
+    assert!((rms_fraction(PI / 2.0) - 0.5).abs() < 0.0001);
+    // Out of range
+    assert_eq!(rms_fraction(-1.0), 1.0);
+}
+
+#[test]
+fn test_firing_angle() {
+    assert!(firing_angle(1.0).abs() < 0.0001);
+    assert!((firing_angle(0.0) - PI).abs() < 0.0001);
+    assert!((firing_angle(0.5) - PI / 2.0).abs() < 0.0001);
+
+    for p in 0..=100 {
+        let fraction = p as f32 / 100.0;
+
+        assert!((rms_fraction(firing_angle(fraction)) - fraction).abs() < 0.0001);
+    }
+}
+
+#[test]
+fn test_rms_to_conduction() {
+    assert_eq!(rms_to_conduction(0), 0);
+    assert_eq!(rms_to_conduction(50), 50);
+    assert_eq!(rms_to_conduction(100), 100);
+    // Low power needs a lot of time
+    assert_eq!(rms_to_conduction(1), 12);
+    assert_eq!(rms_to_conduction(10), 26);
+    assert_eq!(rms_to_conduction(90), 74);
+    // Out of range
+    assert_eq!(rms_to_conduction(150), 100);
+
+    // Curve is monotonic
+    for p in 1..=100 {
+        assert!(rms_to_conduction(p) >= rms_to_conduction(p - 1));
+    }
+}
+
+#[test]
+fn test_conduction_to_rms() {
+    assert_eq!(conduction_to_rms(0), 0);
+    assert_eq!(conduction_to_rms(50), 50);
+    assert_eq!(conduction_to_rms(100), 100);
+    assert_eq!(conduction_to_rms(10), 1);
+    assert_eq!(conduction_to_rms(26), 10);
+
+    for p in 0..=100 {
+        assert!(conduction_to_rms(rms_to_conduction(p)).abs_diff(p) <= 1);
+    }
+}
+
+#[test]
+fn test_power_mode() {
+    assert_eq!(PowerMode::default(), PowerMode::Time);
+    assert_eq!(PowerMode::Time.conduction(10), 10);
+    assert_eq!(PowerMode::Rms.conduction(10), 26);
+}
+
+// Measures of 60W load in doc/HOW-IT-WORKS.md
+const MEASURES: [(u8, f32); 11] = [
+    (50, 33.201),
+    (45, 27.5635),
+    (40, 21.5064),
+    (35, 15.8699),
+    (30, 11.0848),
+    (25, 7.0985),
+    (20, 4.0347),
+    (15, 1.9713),
+    (10, 0.6644),
+    (9, 0.0),
+    (8, 0.0),
+];
+
+#[test]
+fn test_calibration_table_from_slice() {
+    let values: Vec<u8> = (0..=100).rev().collect();
+    let table = CalibrationTable::from_slice(&values).unwrap();
+
+    assert_eq!(table.conduction(0), 100);
+    assert_eq!(table.conduction(100), 0);
+    assert_eq!(table.conduction(200), 0);
+
+    match CalibrationTable::from_slice(&values[1..]) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidCalibration),
+    }
+}
+
+#[test]
+fn test_calibration_table_from_measurements() {
+    let table = CalibrationTable::from_measurements(&MEASURES, 60.0).unwrap();
+
+    assert_eq!(table.conduction(0), 0);
+    // 0.6W, dead zone is skipped
+    assert_eq!(table.conduction(1), 10);
+    // 6W
+    assert_eq!(table.conduction(10), 23);
+    // 30W
+    assert_eq!(table.conduction(50), 47);
+    // 33W
+    assert_eq!(table.conduction(55), 50);
+    // Interpolated to 60W at 100%
+    assert_eq!(table.conduction(100), 100);
+
+    for p in 1..=100 {
+        assert!(table.conduction(p) >= table.conduction(p - 1));
+    }
+}
+
+#[test]
+fn test_calibration_table_saturation() {
+    // Load reach full power at 80% of time
+    let table = CalibrationTable::from_measurements(&[(50, 500.0), (80, 1000.0)], 1000.0).unwrap();
+
+    assert_eq!(table.conduction(50), 50);
+    assert_eq!(table.conduction(100), 80);
+}
+
+#[test]
+fn test_calibration_table_invalid_measurements() {
+    let errors = [
+        CalibrationTable::from_measurements(&[], 60.0),
+        CalibrationTable::from_measurements(&MEASURES, 0.0),
+        CalibrationTable::from_measurements(&[(120, 10.0)], 60.0),
+        CalibrationTable::from_measurements(&[(50, -1.0)], 60.0),
+        CalibrationTable::from_measurements(&[(50, f32::NAN)], 60.0),
+    ];
+
+    for e in errors {
+        match e {
+            Ok(_) => panic!(),
+            Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::InvalidCalibration),
+        }
+    }
+}
+
+#[test]
+fn test_power_mode_calibrated() {
+    let table = CalibrationTable::from_measurements(&MEASURES, 60.0).unwrap();
+
+    assert_eq!(PowerMode::Calibrated(table).conduction(55), 50);
+}
+
+#[test]
+fn test_rms_to_conduction_permille() {
+    assert_eq!(rms_to_conduction_permille(0), 0);
+    assert_eq!(rms_to_conduction_permille(500), 500);
+    assert_eq!(rms_to_conduction_permille(1000), 1000);
+    assert_eq!(rms_to_conduction_permille(5), 92);
+    assert_eq!(rms_to_conduction_permille(100), 259);
+    // Out of range
+    assert_eq!(rms_to_conduction_permille(2000), 1000);
+
+    for p in 1..=1000 {
+        assert!(rms_to_conduction_permille(p) >= rms_to_conduction_permille(p - 1));
+    }
+}
+
+#[test]
+fn test_calibration_table_permille() {
+    let values: Vec<u8> = (0..=100).map(|p| p / 2).collect();
+    let table = CalibrationTable::from_slice(&values).unwrap();
+
+    assert_eq!(table.conduction_permille(0), 0);
+    // 1% = 0%, 2% = 1%
+    assert_eq!(table.conduction_permille(15), 5);
+    assert_eq!(table.conduction_permille(20), 10);
+    assert_eq!(table.conduction_permille(1000), 500);
+    assert_eq!(table.conduction_permille(1500), 500);
+
+    assert_eq!(PowerMode::Time.conduction_permille(125), 125);
+    assert_eq!(PowerMode::Time.conduction_permille(1500), 1000);
+    assert_eq!(PowerMode::Calibrated(table).conduction_permille(15), 5);
+}
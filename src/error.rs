@@ -22,6 +22,32 @@ pub enum RbdDimmerErrorKind {
     DimmerNotFound,
     /// Not init
     DimmerManagerNotInit,
+    /// Singleton already init
+    DimmerManagerAlreadyInit,
+    /// Frequency cannot be measured on zero crossing pin
+    FrequencyNotDetected,
+    /// Calibration table or measures are not valid
+    InvalidCalibration,
+    /// Power requested is out of limits of device
+    PowerOutOfRange,
+    /// Min and max power are not valid
+    InvalidPowerLimits,
+    /// Step size of config is not valid
+    InvalidStepSize,
+    /// Tick max of config is not valid
+    InvalidTickMax,
+    /// Zero crossing offset of config is not valid
+    InvalidZeroCrossingOffset,
+    /// No zero crossing detected during timeout
+    MainsLost,
+    /// Timeout of zero crossing watchdog is not valid
+    InvalidMainsTimeout,
+    /// Window or pulse width of zero crossing filter is not valid
+    InvalidGlitchFilter,
+    /// A device with same ID is already managed
+    DuplicateDeviceId,
+    /// Timeout of frequency detection or offset calibration is not valid
+    InvalidDetectionTimeout,
 }
 
 /// Uart error with type and message
@@ -0,0 +1,178 @@
+//! Fades between power levels
+//!
+//! A fade moves a device from one power to another during a number of half
+//! sinusoidal. Fade is advanced by the manager on each zero crossing, so it doesn't
+//! need any timer or thread.
+//!
+//! A soft-start limits time of half sinusoidal when device is turned on, to avoid
+//! inrush current of cold filaments and motors.
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+// Exponential easing: 2^(10x) from 1 to 1024
+const EXPONENTIAL_BASE: f32 = 1024.0;
+
+/// Shape of a fade.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    /// Power change at constant speed.
+    #[default]
+    Linear,
+    /// Power change slowly at start and end of fade.
+    EaseInOut,
+    /// Power change slowly at start and fast at end. Looks linear for eyes on a lamp.
+    Exponential,
+}
+
+impl Easing {
+    /// Progress of fade (0.0 to 1.0) for a time progress (0.0 to 1.0).
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => x,
+            Easing::EaseInOut => x * x * (3.0 - 2.0 * x),
+            Easing::Exponential => (EXPONENTIAL_BASE.powf(x) - 1.0) / (EXPONENTIAL_BASE - 1.0),
+        }
+    }
+}
+
+/// Number of half sinusoidal during `duration`, with half sinusoidal of `half_period`
+/// µs.
+pub fn half_cycles(duration: Duration, half_period: u32) -> u32 {
+    if half_period == 0 {
+        return 0;
+    }
+
+    (duration.as_micros() / half_period as u128).min(u32::MAX as u128) as u32
+}
+
+/// A fade in progress. Powers are kept in per-mille.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fade {
+    from: u16,
+    to: u16,
+    // Duration of fade in half sinusoidal
+    half_cycles: u32,
+    // Half sinusoidal since fade start
+    elapsed: u32,
+    easing: Easing,
+}
+
+impl Fade {
+    /// Create a fade from power `from` to power `to` (in percent) in `half_cycles` half
+    /// sinusoidal.
+    pub fn new(from: u8, to: u8, half_cycles: u32, easing: Easing) -> Self {
+        Self::new_permille(from as u16 * 10, to as u16 * 10, half_cycles, easing)
+    }
+
+    /// Create a fade between powers in per-mille. A fade from a per-mille power doesn't
+    /// jump to nearest percent first.
+    pub fn new_permille(from: u16, to: u16, half_cycles: u32, easing: Easing) -> Self {
+        Self {
+            from,
+            to,
+            half_cycles,
+            elapsed: 0,
+            easing,
+        }
+    }
+
+    /// Advance fade of one half sinusoidal. Return new power.
+    pub fn step(&mut self) -> u8 {
+        if !self.is_done() {
+            self.elapsed += 1;
+        }
+
+        self.power()
+    }
+
+    /// Power at current time of fade.
+    pub fn power(&self) -> u8 {
+        (self.current() / 10.0).round() as u8
+    }
+
+    /// Power in per-mille at current time of fade. Smoother than `power()` for slow
+    /// fades at low power.
+    pub fn power_permille(&self) -> u16 {
+        self.current().round() as u16
+    }
+
+    /// Target power of fade (rounded percent).
+    pub fn target(&self) -> u8 {
+        ((self.to + 5) / 10) as u8
+    }
+
+    /// Target power of fade in per-mille.
+    pub fn target_permille(&self) -> u16 {
+        self.to
+    }
+
+    // Power in per-mille, not rounded
+    fn current(&self) -> f32 {
+        if self.is_done() {
+            return self.to as f32;
+        }
+
+        let progress = self
+            .easing
+            .apply(self.elapsed as f32 / self.half_cycles as f32);
+        let delta = self.to as f32 - self.from as f32;
+
+        self.from as f32 + delta * progress
+    }
+
+    /// Fade reach target.
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.half_cycles
+    }
+}
+
+/// Soft-start profile of a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftStart {
+    // Duration of ramp in half sinusoidal
+    half_cycles: u32,
+    // Half sinusoidal since device is turned on
+    elapsed: u32,
+}
+
+impl SoftStart {
+    /// Create a soft-start that ramps during `half_cycles` half sinusoidal. Ramp starts
+    /// with `restart()`: device stays off until next zero crossing, then time grows of
+    /// `1 / half_cycles` at each half sinusoidal.
+    pub fn new(half_cycles: u32) -> Self {
+        Self {
+            half_cycles,
+            elapsed: half_cycles,
+        }
+    }
+
+    /// Device is turned on, start ramp.
+    pub fn restart(&mut self) {
+        self.elapsed = 0;
+    }
+
+    /// Advance ramp of one half sinusoidal.
+    pub fn step(&mut self) {
+        if self.is_active() {
+            self.elapsed += 1;
+        }
+    }
+
+    /// Ramp is in progress.
+    pub fn is_active(&self) -> bool {
+        self.elapsed < self.half_cycles
+    }
+
+    /// Time of half sinusoidal allowed by ramp for a requested time (in any unit).
+    pub fn limit(&self, conduction: u16) -> u16 {
+        if !self.is_active() {
+            return conduction;
+        }
+
+        (conduction as u32 * self.elapsed / self.half_cycles) as u16
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use crate::fade::*;
+
+#[test]
+fn test_easing() {
+    for easing in [Easing::Linear, Easing::EaseInOut, Easing::Exponential] {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+        assert_eq!(easing.apply(2.0), 1.0);
+
+        // Easing is monotonic
+        for i in 1..=100 {
+            assert!(easing.apply(i as f32 / 100.0) >= easing.apply((i - 1) as f32 / 100.0));
+        }
+    }
+
+    assert_eq!(Easing::Linear.apply(0.25), 0.25);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    assert!(Easing::EaseInOut.apply(0.1) < 0.1);
+    assert!(Easing::EaseInOut.apply(0.9) > 0.9);
+    assert!(Easing::Exponential.apply(0.5) < 0.05);
+}
+
+#[test]
+fn test_half_cycles() {
+    assert_eq!(half_cycles(Duration::from_secs(1), 10_000), 100);
+    assert_eq!(half_cycles(Duration::from_secs(1), 8_333), 120);
+    assert_eq!(half_cycles(Duration::from_millis(5), 10_000), 0);
+    assert_eq!(half_cycles(Duration::from_secs(1), 0), 0);
+}
+
+#[test]
+fn test_fade_linear() {
+    let mut fade = Fade::new(0, 100, 10, Easing::Linear);
+
+    assert_eq!(fade.power(), 0);
+    assert_eq!(fade.target(), 100);
+    assert!(!fade.is_done());
+
+    for i in 1..10 {
+        assert_eq!(fade.step(), i * 10);
+        assert!(!fade.is_done());
+    }
+
+    assert_eq!(fade.step(), 100);
+    assert!(fade.is_done());
+
+    // Fade is finished
+    assert_eq!(fade.step(), 100);
+}
+
+#[test]
+fn test_fade_down() {
+    let mut fade = Fade::new(80, 20, 4, Easing::Linear);
+
+    assert_eq!(fade.step(), 65);
+    assert_eq!(fade.step(), 50);
+    assert_eq!(fade.step(), 35);
+    assert_eq!(fade.step(), 20);
+    assert!(fade.is_done());
+}
+
+#[test]
+fn test_fade_ease_in_out() {
+    let mut fade = Fade::new(0, 100, 4, Easing::EaseInOut);
+
+    assert_eq!(fade.step(), 16);
+    assert_eq!(fade.step(), 50);
+    assert_eq!(fade.step(), 84);
+    assert_eq!(fade.step(), 100);
+}
+
+#[test]
+fn test_fade_no_duration() {
+    let fade = Fade::new(10, 60, 0, Easing::Exponential);
+
+    assert!(fade.is_done());
+    assert_eq!(fade.power(), 60);
+}
+
+#[test]
+fn test_soft_start() {
+    let mut soft_start = SoftStart::new(4);
+
+    // Not started
+    assert!(!soft_start.is_active());
+    assert_eq!(soft_start.limit(80), 80);
+
+    soft_start.restart();
+
+    // Off until next zero crossing
+    assert!(soft_start.is_active());
+    assert_eq!(soft_start.limit(80), 0);
+
+    soft_start.step();
+    assert_eq!(soft_start.limit(80), 20);
+
+    soft_start.step();
+    assert_eq!(soft_start.limit(80), 40);
+
+    soft_start.step();
+    assert_eq!(soft_start.limit(80), 60);
+
+    soft_start.step();
+    assert!(!soft_start.is_active());
+    assert_eq!(soft_start.limit(80), 80);
+
+    soft_start.step();
+    assert_eq!(soft_start.limit(100), 100);
+}
+
+#[test]
+fn test_soft_start_disabled() {
+    let mut soft_start = SoftStart::new(0);
+
+    soft_start.restart();
+
+    assert!(!soft_start.is_active());
+    assert_eq!(soft_start.limit(50), 50);
+}
+
+#[test]
+fn test_fade_new_permille() {
+    let mut fade = Fade::new_permille(123, 523, 4, Easing::Linear);
+
+    // No jump to 12%
+    assert_eq!(fade.power_permille(), 123);
+    assert_eq!(fade.power(), 12);
+    assert_eq!(fade.target(), 52);
+    assert_eq!(fade.target_permille(), 523);
+
+    assert_eq!(fade.step(), 22);
+    assert_eq!(fade.power_permille(), 223);
+}
+
+#[test]
+fn test_fade_power_permille() {
+    let mut fade = Fade::new(1, 2, 4, Easing::Linear);
+
+    assert_eq!(fade.power_permille(), 10);
+
+    fade.step();
+    assert_eq!(fade.power_permille(), 13);
+
+    fade.step();
+    assert_eq!(fade.power_permille(), 15);
+
+    fade.step();
+    fade.step();
+    assert_eq!(fade.power_permille(), 20);
+}
@@ -0,0 +1,99 @@
+//! Output pin faults
+//!
+//! Timer cannot return errors. When an output pin fails, the error is recorded in the
+//! `FaultState` of the device: counter of errors and a latched error, kept until it's
+//! cleared. `FaultPolicy` tells what manager does on error.
+//!
+//! Errors are reported to application by a callback, called out of ISR (on zero
+//! crossing).
+use crate::error::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Callback called with id of device and error when a device faults.
+pub type FaultCallback = Box<dyn FnMut(u8, &RbdDimmerError) + Send>;
+
+/// What to do when an output pin fails.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FaultPolicy {
+    /// Error is recorded, device continues.
+    #[default]
+    Ignore,
+    /// Device is not driven anymore until fault is cleared.
+    DisableDevice,
+    /// All devices are turned off until fault is cleared.
+    StopAll,
+}
+
+/// Faults of a device.
+#[derive(Debug, Clone, Default)]
+pub struct FaultState {
+    // Number of errors since start
+    count: u32,
+    // First error not cleared
+    latched: Option<RbdDimmerError>,
+    // Device is not driven
+    disabled: bool,
+    // Latched error is sent to callback
+    reported: bool,
+}
+
+impl FaultState {
+    /// Create state without fault.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an error.
+    pub fn record(&mut self, error: RbdDimmerError) {
+        self.count = self.count.saturating_add(1);
+
+        if self.latched.is_none() {
+            self.latched = Some(error);
+            self.reported = false;
+        }
+    }
+
+    /// Number of errors since start.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// An error is latched.
+    pub fn is_latched(&self) -> bool {
+        self.latched.is_some()
+    }
+
+    /// Latched error.
+    pub fn error(&self) -> Option<&RbdDimmerError> {
+        self.latched.as_ref()
+    }
+
+    /// Device is not driven because of fault.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Don't drive device anymore.
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+
+    /// Clear latched error and enable device. Counter is kept.
+    pub fn clear(&mut self) {
+        self.latched = None;
+        self.disabled = false;
+        self.reported = false;
+    }
+
+    /// Latched error if not already reported.
+    pub fn take_report(&mut self) -> Option<RbdDimmerError> {
+        if self.reported {
+            return None;
+        }
+
+        self.reported = true;
+        self.latched.clone()
+    }
+}
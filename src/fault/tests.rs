@@ -0,0 +1,42 @@
+use crate::fault::*;
+
+#[test]
+fn test_fault_state() {
+    let mut fault = FaultState::new();
+
+    assert_eq!(fault.count(), 0);
+    assert!(!fault.is_latched());
+    assert!(fault.take_report().is_none());
+
+    fault.record(RbdDimmerError::from(RbdDimmerErrorKind::SetHigh));
+    fault.record(RbdDimmerError::from(RbdDimmerErrorKind::SetLow));
+
+    assert_eq!(fault.count(), 2);
+    assert!(fault.is_latched());
+    // First error is kept
+    assert_eq!(fault.error().unwrap().kind, RbdDimmerErrorKind::SetHigh);
+
+    // Reported once
+    assert_eq!(
+        fault.take_report().unwrap().kind,
+        RbdDimmerErrorKind::SetHigh
+    );
+    assert!(fault.take_report().is_none());
+
+    fault.disable();
+
+    assert!(fault.is_disabled());
+
+    fault.clear();
+
+    assert!(!fault.is_latched());
+    assert!(!fault.is_disabled());
+    assert_eq!(fault.count(), 2);
+
+    fault.record(RbdDimmerError::from(RbdDimmerErrorKind::SetLow));
+
+    assert_eq!(
+        fault.take_report().unwrap().kind,
+        RbdDimmerErrorKind::SetLow
+    );
+}
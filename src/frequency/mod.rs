@@ -0,0 +1,164 @@
+//! Frequency of electricity
+//!
+//! Nominal frequencies and detection of frequency from zero crossing timestamps.
+//! A zero crossing happens every half sinusoidal, so at 50Hz, two edges are
+//! separated by 10ms.
+use crate::error::*;
+use core::fmt;
+
+#[cfg(test)]
+mod tests;
+
+// Duration of each percent cycle.
+// 50Hz => half sinusoidal / 100 = 0.1 ms
+const HZ_50_DURATION: u8 = 100;
+// 60Hz => half sinusoidal / 100 = 0.083 ms
+const HZ_60_DURATION: u8 = 83;
+// Half sinusoidal duration in µs.
+const HZ_50_HALF_PERIOD: u32 = 10_000;
+const HZ_60_HALF_PERIOD: u32 = 8_333;
+// Half period limit (in µs) between 50Hz and 60Hz (55Hz).
+const HALF_PERIOD_50_60_LIMIT: u32 = 9_091;
+// Lowest accepted frequency (45Hz).
+const HALF_PERIOD_MAX: u32 = 11_111;
+// Highest accepted frequency (65Hz).
+const HALF_PERIOD_MIN: u32 = 7_692;
+
+/// This enum represent the frequency electricity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frequency {
+    /// Voltage has 50Hz frequency (like Europe).
+    F50HZ,
+    /// Voltage haz 60Hz frequency (like U.K.).
+    F60HZ,
+    /// Frequency is measured on zero crossing pin at startup.
+    Auto,
+}
+
+/// Similarly, implement `Display` for `Frequency`.
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frequency::F50HZ => write!(f, "50Hz"),
+            Frequency::F60HZ => write!(f, "60Hz"),
+            Frequency::Auto => write!(f, "Auto"),
+        }
+    }
+}
+
+impl Frequency {
+    /// Duration in µs of each percent of half sinusoidal. `None` if frequency is not
+    /// detected yet.
+    pub fn step_duration(&self) -> Option<u8> {
+        match self {
+            Frequency::F50HZ => Some(HZ_50_DURATION),
+            Frequency::F60HZ => Some(HZ_60_DURATION),
+            Frequency::Auto => None,
+        }
+    }
+
+    /// Duration in µs of half sinusoidal. `None` if frequency is not detected yet.
+    pub fn half_period(&self) -> Option<u32> {
+        match self {
+            Frequency::F50HZ => Some(HZ_50_HALF_PERIOD),
+            Frequency::F60HZ => Some(HZ_60_HALF_PERIOD),
+            Frequency::Auto => None,
+        }
+    }
+
+    /// Nominal frequency of a half period in µs. `None` if half period is not between
+    /// 45Hz and 65Hz.
+    pub fn from_half_period(half_period: u32) -> Option<Frequency> {
+        if !(HALF_PERIOD_MIN..=HALF_PERIOD_MAX).contains(&half_period) {
+            None
+        } else if half_period > HALF_PERIOD_50_60_LIMIT {
+            Some(Frequency::F50HZ)
+        } else {
+            Some(Frequency::F60HZ)
+        }
+    }
+}
+
+/// Measure frequency from zero crossing edges.
+pub struct FrequencyDetector {
+    // Number of half sinusoidal to measure
+    half_cycles: u8,
+    // Timestamp of previous edge
+    last_edge: Option<u64>,
+    // Sum of measured half periods
+    total: u64,
+    // Number of measured half periods
+    count: u8,
+}
+
+impl FrequencyDetector {
+    /// Create new detector that measure `half_cycles` half sinusoidal.
+    pub fn new(half_cycles: u8) -> Self {
+        Self {
+            half_cycles: half_cycles.max(1),
+            last_edge: None,
+            total: 0,
+            count: 0,
+        }
+    }
+
+    /// A zero crossing edge happen at `timestamp` (in µs). Return `true` when measure
+    /// is finished.
+    pub fn edge(&mut self, timestamp: u64) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        if let Some(last_edge) = self.last_edge {
+            self.total += timestamp.saturating_sub(last_edge);
+            self.count += 1;
+        }
+
+        self.last_edge = Some(timestamp);
+
+        self.is_done()
+    }
+
+    /// Enough half sinusoidal are measured.
+    pub fn is_done(&self) -> bool {
+        self.count >= self.half_cycles
+    }
+
+    /// Average half period in µs.
+    pub fn half_period(&self) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some((self.total / self.count as u64) as u32)
+    }
+
+    /// Measured frequency in Hz.
+    pub fn measured_frequency(&self) -> Option<f32> {
+        match self.half_period() {
+            Some(0) | None => None,
+            Some(h) => Some(1_000_000.0 / (2.0 * h as f32)),
+        }
+    }
+
+    /// Nominal frequency nearest of measured frequency.
+    pub fn frequency(&self) -> Result<Frequency, RbdDimmerError> {
+        if !self.is_done() {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::FrequencyNotDetected,
+                String::from("Not enough zero crossing to detect frequency"),
+            ));
+        }
+
+        match self.half_period().and_then(Frequency::from_half_period) {
+            Some(f) => Ok(f),
+            None => Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::FrequencyNotDetected,
+                format!(
+                    "Measured frequency is not 50Hz or 60Hz: {:?}Hz",
+                    self.measured_frequency()
+                ),
+            )),
+        }
+    }
+}
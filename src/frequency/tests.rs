@@ -0,0 +1,96 @@
+use crate::frequency::*;
+
+fn measure(half_period: u64, edges: u64) -> FrequencyDetector {
+    let mut detector = FrequencyDetector::new(8);
+    let mut timestamp = 1_234;
+
+    for _ in 0..edges {
+        detector.edge(timestamp);
+        timestamp += half_period;
+    }
+
+    detector
+}
+
+#[test]
+fn test_frequency_step_duration() {
+    assert_eq!(Frequency::F50HZ.step_duration(), Some(100));
+    assert_eq!(Frequency::F60HZ.step_duration(), Some(83));
+    assert_eq!(Frequency::Auto.step_duration(), None);
+}
+
+#[test]
+fn test_frequency_half_period() {
+    assert_eq!(Frequency::F50HZ.half_period(), Some(10_000));
+    assert_eq!(Frequency::F60HZ.half_period(), Some(8_333));
+    assert_eq!(Frequency::Auto.half_period(), None);
+}
+
+#[test]
+fn test_frequency_from_half_period() {
+    assert_eq!(Frequency::from_half_period(10_000), Some(Frequency::F50HZ));
+    assert_eq!(Frequency::from_half_period(10_050), Some(Frequency::F50HZ));
+    assert_eq!(Frequency::from_half_period(8_333), Some(Frequency::F60HZ));
+    assert_eq!(Frequency::from_half_period(8_200), Some(Frequency::F60HZ));
+    assert_eq!(Frequency::from_half_period(5_000), None);
+    assert_eq!(Frequency::from_half_period(20_000), None);
+}
+
+#[test]
+fn test_detector_50_hz() {
+    let detector = measure(10_000, 9);
+
+    assert!(detector.is_done());
+    assert_eq!(detector.half_period(), Some(10_000));
+    assert_eq!(detector.measured_frequency(), Some(50.0));
+    assert_eq!(detector.frequency().unwrap(), Frequency::F50HZ);
+}
+
+#[test]
+fn test_detector_60_hz() {
+    let detector = measure(8_333, 9);
+
+    assert!(detector.is_done());
+    assert_eq!(detector.frequency().unwrap(), Frequency::F60HZ);
+}
+
+#[test]
+fn test_detector_jitter() {
+    let mut detector = FrequencyDetector::new(4);
+
+    assert!(!detector.edge(0));
+    assert!(!detector.edge(9_900));
+    assert!(!detector.edge(20_100));
+    assert!(!detector.edge(29_950));
+    assert!(detector.edge(40_000));
+
+    assert_eq!(detector.half_period(), Some(10_000));
+    assert_eq!(detector.frequency().unwrap(), Frequency::F50HZ);
+
+    // Measure is finished, new edge are ignored
+    assert!(detector.edge(100_000));
+    assert_eq!(detector.half_period(), Some(10_000));
+}
+
+#[test]
+fn test_detector_not_enough_edges() {
+    let detector = measure(10_000, 3);
+
+    assert!(!detector.is_done());
+
+    match detector.frequency() {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::FrequencyNotDetected),
+    }
+}
+
+#[test]
+fn test_detector_bad_frequency() {
+    // 25Hz, maybe ZC circuit give only one pulse per period
+    let detector = measure(20_000, 9);
+
+    match detector.frequency() {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::FrequencyNotDetected),
+    }
+}
@@ -0,0 +1,89 @@
+//! Zero crossing glitch filter
+//!
+//! Switching inductive loads puts spikes on zero crossing line. Each spike seen as an
+//! edge restarts half sinusoidal and causes a mis-fire. An edge is accepted only if:
+//! - it arrives after a part (`window`) of expected half period since last accepted
+//!   edge,
+//! - its pulse is wider than `min_pulse_width` (if width is known).
+//!
+//! Timestamps and widths are in µs.
+
+#[cfg(test)]
+mod tests;
+
+/// Default part of half period (in percent) before an edge is accepted.
+pub const DEFAULT_EDGE_WINDOW: u8 = 75;
+/// Default minimal width of zero crossing pulse in µs.
+pub const DEFAULT_MIN_PULSE_WIDTH: u32 = 100;
+
+/// Reject zero crossing edges that are too early or too short.
+#[derive(Debug, Clone)]
+pub struct GlitchFilter {
+    // Expected half period in µs
+    half_period: u32,
+    // Percent of half period before an edge is accepted. 0 = no check.
+    window: u8,
+    // Minimal pulse width in µs. 0 = no check.
+    min_pulse_width: u32,
+    // Timestamp of last accepted edge
+    last_edge: Option<u64>,
+    // Number of rejected edges
+    rejected: u32,
+}
+
+impl GlitchFilter {
+    /// Create filter for expected `half_period` (µs) with default window and pulse width.
+    pub fn new(half_period: u32) -> Self {
+        Self {
+            half_period,
+            window: DEFAULT_EDGE_WINDOW,
+            min_pulse_width: DEFAULT_MIN_PULSE_WIDTH,
+            last_edge: None,
+            rejected: 0,
+        }
+    }
+
+    /// Set part of half period (in percent) before an edge is accepted. 0 disables check.
+    pub fn with_window(mut self, window: u8) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Set minimal pulse width in µs. 0 disables check.
+    pub fn with_min_pulse_width(mut self, min_pulse_width: u32) -> Self {
+        self.min_pulse_width = min_pulse_width;
+        self
+    }
+
+    /// Update expected half period, for example tracked by PLL.
+    pub fn set_half_period(&mut self, half_period: u32) {
+        self.half_period = half_period;
+    }
+
+    /// Minimal time in µs between two accepted edges.
+    pub fn min_interval(&self) -> u32 {
+        (self.half_period as u64 * self.window as u64 / 100) as u32
+    }
+
+    /// Edge at `timestamp` with pulse of `pulse_width` µs (`None` if unknown). Return
+    /// `true` if edge is a zero crossing.
+    pub fn edge(&mut self, timestamp: u64, pulse_width: Option<u32>) -> bool {
+        let too_short = matches!(pulse_width, Some(w) if w < self.min_pulse_width);
+        let too_early = matches!(self.last_edge,
+            Some(last) if timestamp.saturating_sub(last) < self.min_interval() as u64);
+
+        if too_short || too_early {
+            self.rejected = self.rejected.saturating_add(1);
+            return false;
+        }
+
+        self.last_edge = Some(timestamp);
+
+        true
+    }
+
+    /// Number of rejected edges.
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+}
@@ -0,0 +1,59 @@
+use crate::glitch::*;
+
+#[test]
+fn test_glitch_filter_accept_regular_edges() {
+    let mut filter = GlitchFilter::new(10_000);
+
+    assert_eq!(filter.min_interval(), 7_500);
+
+    for i in 0..10 {
+        assert!(filter.edge(i * 10_000, Some(400)));
+    }
+
+    assert_eq!(filter.rejected(), 0);
+}
+
+#[test]
+fn test_glitch_filter_reject_early_edge() {
+    let mut filter = GlitchFilter::new(10_000);
+
+    assert!(filter.edge(0, None));
+    // Spike
+    assert!(!filter.edge(3_000, None));
+    assert!(!filter.edge(7_499, None));
+    // Measured from last accepted edge
+    assert!(filter.edge(10_000, None));
+    assert!(filter.edge(19_900, None));
+
+    assert_eq!(filter.rejected(), 2);
+}
+
+#[test]
+fn test_glitch_filter_reject_short_pulse() {
+    let mut filter = GlitchFilter::new(10_000).with_min_pulse_width(200);
+
+    assert!(!filter.edge(0, Some(20)));
+    assert!(filter.edge(100, Some(200)));
+    assert!(!filter.edge(10_100, Some(199)));
+    assert!(filter.edge(10_150, Some(400)));
+
+    assert_eq!(filter.rejected(), 2);
+}
+
+#[test]
+fn test_glitch_filter_disabled() {
+    let mut filter = GlitchFilter::new(10_000)
+        .with_window(0)
+        .with_min_pulse_width(0);
+
+    assert!(filter.edge(0, Some(1)));
+    assert!(filter.edge(1, Some(0)));
+
+    // PLL tracks a new half period
+    let mut filter = GlitchFilter::new(10_000);
+
+    filter.set_half_period(8_333);
+
+    assert!(filter.edge(0, None));
+    assert!(filter.edge(6_300, None));
+}
@@ -0,0 +1,155 @@
+//! Zero crossing in GPIO interrupt
+//!
+//! With `ZeroCrossingMode::Task`, application waits zero crossing in a loop. Latency of
+//! task scheduler becomes phase error. With `ZeroCrossingMode::Interrupt`, zero crossing
+//! pin raises an interrupt on each edge and `ZeroCrossingInterrupt` handles it directly:
+//! - rising edge is start of pulse,
+//! - falling edge is zero crossing, checked by glitch filter (see `glitch` module).
+//!
+//! What to do on an accepted zero crossing is abstract (`ZeroCrossingHandler`). On ESP,
+//! handler posts timestamp in a `ZeroCrossingMailbox` and wakes a driver task, that
+//! updates PLL and starts half sinusoidal of engine. Interrupt only writes atomics: it
+//! never locks, allocates or uses FPU. On host, a fake handler can be tested.
+//!
+//! Timestamps are in µs.
+use crate::glitch::GlitchFilter;
+use std::sync::atomic::{fence, AtomicU32, Ordering as aOrdering};
+
+#[cfg(test)]
+mod tests;
+
+/// How zero crossing pin is read.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ZeroCrossingMode {
+    /// Application calls `wait_zero_crossing()` in a loop.
+    #[default]
+    Task,
+    /// Zero crossing is handled in GPIO interrupt. No loop is needed.
+    Interrupt,
+}
+
+/// Called in interrupt when a zero crossing is accepted. Keep it short.
+pub trait ZeroCrossingHandler {
+    /// Half period of electricity in µs, to check edges.
+    fn half_period(&self) -> u32;
+
+    /// Zero crossing at `timestamp`.
+    fn zero_crossing(&self, timestamp: u64);
+}
+
+/// Handle edges of zero crossing pin in interrupt.
+#[derive(Debug, Clone)]
+pub struct ZeroCrossingInterrupt {
+    // Reject spikes
+    glitch_filter: GlitchFilter,
+    // Timestamp of last rising edge
+    rising: Option<u64>,
+}
+
+impl ZeroCrossingInterrupt {
+    /// Create interrupt handler with glitch filter.
+    pub fn new(glitch_filter: GlitchFilter) -> Self {
+        Self {
+            glitch_filter,
+            rising: None,
+        }
+    }
+
+    /// Edge of zero crossing pin at `timestamp`. `high` is level of pin after edge.
+    /// Return `true` if edge is an accepted zero crossing and `handler` was called.
+    pub fn edge<H: ZeroCrossingHandler>(
+        &mut self,
+        handler: &H,
+        high: bool,
+        timestamp: u64,
+    ) -> bool {
+        if high {
+            self.rising = Some(timestamp);
+            return false;
+        }
+
+        // Width is unknown if rising edge is missed
+        let width = self
+            .rising
+            .take()
+            .map(|start| timestamp.saturating_sub(start) as u32);
+
+        self.glitch_filter.set_half_period(handler.half_period());
+
+        if !self.glitch_filter.edge(timestamp, width) {
+            return false;
+        }
+
+        handler.zero_crossing(timestamp);
+
+        true
+    }
+
+    /// Number of edges rejected by glitch filter.
+    pub fn rejected_crossings(&self) -> u32 {
+        self.glitch_filter.rejected()
+    }
+}
+
+/// Last zero crossing posted by interrupt, read by driver task. Interrupt never waits:
+/// driver reads again if a zero crossing is posted while it reads (sequence lock).
+#[derive(Debug, Default)]
+pub struct ZeroCrossingMailbox {
+    // Odd while a zero crossing is posted
+    sequence: AtomicU32,
+    // Timestamp of last zero crossing, wraps
+    timestamp: AtomicU32,
+    // Number of edges rejected by glitch filter
+    rejected: AtomicU32,
+}
+
+impl ZeroCrossingMailbox {
+    /// Create empty mailbox.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Post zero crossing at `timestamp`, in interrupt. If previous one is not taken, it
+    /// is replaced.
+    pub fn post(&self, timestamp: u32) {
+        self.sequence.fetch_add(1, aOrdering::Relaxed);
+        fence(aOrdering::Release);
+        self.timestamp.store(timestamp, aOrdering::Relaxed);
+        self.sequence.fetch_add(1, aOrdering::Release);
+    }
+
+    /// Take zero crossing posted since `seen`, sequence of last taken one (0 at start).
+    pub fn take(&self, seen: &mut u32) -> Option<u32> {
+        loop {
+            let sequence = self.sequence.load(aOrdering::Acquire);
+
+            if sequence == *seen {
+                return None;
+            }
+
+            if sequence % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let timestamp = self.timestamp.load(aOrdering::Relaxed);
+
+            fence(aOrdering::Acquire);
+
+            if self.sequence.load(aOrdering::Relaxed) == sequence {
+                *seen = sequence;
+                return Some(timestamp);
+            }
+        }
+    }
+
+    /// Set number of edges rejected by glitch filter, in interrupt.
+    pub fn set_rejected(&self, rejected: u32) {
+        self.rejected.store(rejected, aOrdering::Relaxed);
+    }
+
+    /// Number of edges rejected by glitch filter.
+    pub fn rejected(&self) -> u32 {
+        self.rejected.load(aOrdering::Relaxed)
+    }
+}
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+
+use crate::glitch::GlitchFilter;
+use crate::interrupt::*;
+
+// Record zero crossings like tick counter reset
+struct FakeHandler {
+    crossings: RefCell<Vec<u64>>,
+}
+
+impl FakeHandler {
+    fn new() -> Self {
+        Self {
+            crossings: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl ZeroCrossingHandler for FakeHandler {
+    fn half_period(&self) -> u32 {
+        10_000
+    }
+
+    fn zero_crossing(&self, timestamp: u64) {
+        self.crossings.borrow_mut().push(timestamp);
+    }
+}
+
+#[test]
+fn test_interrupt_falling_edge_is_zero_crossing() {
+    let handler = FakeHandler::new();
+    let mut interrupt = ZeroCrossingInterrupt::new(GlitchFilter::new(0));
+
+    for i in 0..5 {
+        assert!(!interrupt.edge(&handler, true, i * 10_000 + 9_600));
+        assert!(interrupt.edge(&handler, false, i * 10_000 + 10_000));
+    }
+
+    assert_eq!(
+        *handler.crossings.borrow(),
+        vec![10_000, 20_000, 30_000, 40_000, 50_000]
+    );
+    assert_eq!(interrupt.rejected_crossings(), 0);
+}
+
+#[test]
+fn test_interrupt_reject_glitch() {
+    let handler = FakeHandler::new();
+    let mut interrupt = ZeroCrossingInterrupt::new(GlitchFilter::new(0));
+
+    assert!(!interrupt.edge(&handler, true, 9_600));
+    assert!(interrupt.edge(&handler, false, 10_000));
+
+    // Spike in half sinusoidal
+    assert!(!interrupt.edge(&handler, true, 13_000));
+    assert!(!interrupt.edge(&handler, false, 13_020));
+
+    // Pulse too short
+    assert!(!interrupt.edge(&handler, true, 19_000));
+    assert!(!interrupt.edge(&handler, false, 19_020));
+
+    assert!(!interrupt.edge(&handler, true, 19_700));
+    assert!(interrupt.edge(&handler, false, 20_100));
+
+    assert_eq!(*handler.crossings.borrow(), vec![10_000, 20_100]);
+    assert_eq!(interrupt.rejected_crossings(), 2);
+}
+
+#[test]
+fn test_interrupt_missed_rising_edge() {
+    let handler = FakeHandler::new();
+    let mut interrupt = ZeroCrossingInterrupt::new(GlitchFilter::new(0));
+
+    // Interrupt enabled during pulse, width is unknown
+    assert!(interrupt.edge(&handler, false, 10_000));
+
+    assert_eq!(*handler.crossings.borrow(), vec![10_000]);
+}
+
+#[test]
+fn test_mailbox() {
+    let mailbox = ZeroCrossingMailbox::new();
+    let mut seen = 0;
+
+    assert_eq!(mailbox.take(&mut seen), None);
+
+    mailbox.post(10_000);
+
+    assert_eq!(mailbox.take(&mut seen), Some(10_000));
+    assert_eq!(mailbox.take(&mut seen), None);
+
+    // Driver is late, only last zero crossing is kept
+    mailbox.post(20_000);
+    mailbox.post(u32::MAX);
+
+    assert_eq!(mailbox.take(&mut seen), Some(u32::MAX));
+    assert_eq!(mailbox.take(&mut seen), None);
+
+    mailbox.set_rejected(3);
+
+    assert_eq!(mailbox.rejected(), 3);
+}
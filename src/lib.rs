@@ -10,19 +10,67 @@
 //! You can use `zc` sub-module that manage % by using half sinusoidal.
 //! The `zc` sub-module works only for 50Hz voltage.
 //! 50Hz = 100 half sinusoidal per seconde => 100%
-use core::fmt;
-use esp_idf_hal::gpio::{AnyInputPin, AnyOutputPin, Input, Output, PinDriver};
+use edge_executor::{LocalExecutor, Task};
+use esp_idf_hal::delay::BLOCK;
+use esp_idf_hal::gpio::{AnyInputPin, AnyOutputPin, Input, InterruptType, Output, PinDriver};
+use esp_idf_hal::interrupt::IsrCriticalSection;
 use esp_idf_hal::task::block_on;
-use esp_idf_svc::timer::{EspISRTimerService, EspTimer};
+use esp_idf_hal::task::notification::{Notification, Notifier};
+use esp_idf_hal::task::thread::ThreadSpawnConfiguration;
+use esp_idf_svc::timer::{EspAsyncTimer, EspISRTimerService, EspTaskTimerService, EspTimer};
 use esp_idf_sys::EspError;
-use std::cell::RefCell;
-use std::cmp::Ordering;
-use std::sync::atomic::{AtomicU8, Ordering as aOrdering};
+use std::cell::UnsafeCell;
+use std::future::{poll_fn, Future};
+use std::num::NonZeroU32;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering as aOrdering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::task::Poll;
+use std::thread::JoinHandle;
 use std::time::Duration;
 
+use crate::config::{TimingConfig, TimingConfigBuilder};
 use crate::error::*;
+use crate::frequency::FrequencyDetector;
+use crate::glitch::GlitchFilter;
+use crate::interrupt::{ZeroCrossingHandler, ZeroCrossingInterrupt, ZeroCrossingMailbox};
+use crate::phase::PhaseAngleEngine;
+use crate::pll::{Clock, OffsetCalibrator, TrackedTick};
+use crate::schedule::{OneShotTimer, Scheduler};
+use crate::stats::MainsStats;
+use crate::target::PowerTarget;
+use crate::watchdog::MainsWatchdog;
 
+pub use crate::curve::{CalibrationTable, PowerMode};
+pub use crate::fade::Easing;
+pub use crate::fault::{FaultCallback, FaultPolicy, FaultState};
+pub use crate::frequency::Frequency;
+pub use crate::interrupt::ZeroCrossingMode;
+pub use crate::limit::{LimitPolicy, PowerLimits};
+pub use crate::phase::{DeviceState, GateDrive, PhaseMode};
+pub use crate::pll::ZeroCrossingOffset;
+pub use crate::schedule::Scheduling;
+pub use crate::stats::MainsStatistics;
+pub use crate::watchdog::{MainsCallback, MainsEvent};
+
+pub mod config;
+pub mod curve;
 pub mod error;
+pub mod fade;
+pub mod fault;
+pub mod frequency;
+pub mod glitch;
+pub mod interrupt;
+pub mod limit;
+pub mod phase;
+pub mod pll;
+pub mod schedule;
+pub mod stats;
+pub mod target;
+#[cfg(test)]
+mod testing;
+pub mod watchdog;
 pub mod zc;
 
 //---------------------------------------------------------------------------------------
@@ -47,334 +95,1758 @@ pub mod zc;
 //
 // In this case, we need do the job very quicly. And to do that, Rust is not really
 // helpfull :)
-// We need use ISR timer. That mean we cannot have context. We need use static global
-// variable.
+// We need use ISR timer. That mean we cannot have context. Each manager share its tick
+// counter and its devices with its ISR timer (the singleton is kept in static global
+// variable for free functions). Devices are only used in a critical section, so a task
+// and ISR timer never use them at same time, even on two cores.
+//
+// When zero crossing is detected, timestamp is given to a software PLL (see `pll`
+// module) that track the half period of electricity. TICK is computed from time
+// elapsed since last zero crossing, from 0 to tick_max (normaly 100 but in this case,
+// we have collision with zero crossing detection). If one or two zero crossing are
+// missed, PLL predicts them.
+//
+// By default (`Scheduling::OneShot`), on each zero crossing, the phase angle engine
+// (see `phase` module) computes ticks where an output changes. The ISR timer is armed
+// once for each of them (see `schedule` module) and call `fire_fine()` of engine, that
+// call `fine_tick()` method of each dimmer. Power is applied at 0.1%. If a zero
+// crossing is missed, plan is repeated on predicted zero crossing.
+//
+// With `Scheduling::Polling`, the ISR timer is always on. It call `advance()` of the
+// engine every tick. Power under 1% is dithered across half sinusoidal.
 //
-// The ISR timer is always on.
-// When zero crossing is detected, we set IS_ZERO_CROSSING to true.
-// When IS_ZERO_CROSSING is true, ISR timer increase TICK from 0 to tick_max (normaly
-// 100 but in this case, we have collision with zero crossing detection).
-// The ISR timer call `tick_manager()` method of each dimmer.
+// With `ZeroCrossingMode::Interrupt`, zero crossing pin raises a GPIO interrupt on each
+// edge (see `interrupt` module). Interrupt only posts timestamp of edge and wakes a
+// driver task of crate, that does the work of `wait_zero_crossing()`. So no application
+// loop is needed and task scheduler latency doesn't shift phase: PLL uses timestamp.
+//
+// Spikes on zero crossing pin are ignored (see `glitch` module): a pulse must be wide
+// enough and arrive after most of half period.
+//
+// Power is never written in engine by tasks. Each device has a power target (see
+// `target` module), an atomic value that ISR reads on next zero crossing. So a power
+// request is never lost, even if ISR timer uses engine or runs on another core.
+//
+// A task timer checks the zero crossing watchdog (see `watchdog` module). If no zero
+// crossing happens during mains timeout, all devices are turned off until next zero
+// crossing. Application is told with `MainsEvent`.
 //---------------------------------------------------------------------------------------
 
-// Duration of each percent cycle.
-// 50Hz => half sinusoidal / 100 = 0.1 ms
-const HZ_50_DURATION: u8 = 100;
-// 60Hz => half sinusoidal / 100 = 0.083 ms
-const HZ_60_DURATION: u8 = 83;
-// Maximal tick value. Cannot work 100% because of the zero crossing detection timer on the same core.
-const DEFAULT_TICK_MAX: u8 = 95;
-// Tick of device timer counter. TICK=0 means zero crossing detected.
-// If TICK=TICK_MAX, nothing happen.
-static TICK: AtomicU8 = AtomicU8::new(0);
+// Number of half sinusoidal measured to detect frequency.
+const DEFAULT_DETECTION_HALF_CYCLES: u8 = 10;
+// Number of zero crossing pulses measured to calibrate offset.
+const DEFAULT_CALIBRATION_PULSES: u8 = 10;
+// Driver task of `ZeroCrossingMode::Interrupt`, under esp_timer task (22)
+const DRIVER_TASK_PRIORITY: u8 = 20;
+const DRIVER_TASK_STACK_SIZE: usize = 4096;
 
 /// Output pin (dimmer).
 pub type OutputPin = PinDriver<'static, AnyOutputPin, Output>;
 /// Input pin (zero crossing).
 pub type InputPin = PinDriver<'static, AnyInputPin, Input>;
 
-struct GlobalDimmerManager {
-    // The device manager
-    manager: RefCell<Option<DevicesDimmerManager>>,
+type Engine = PhaseAngleEngine<OutputPin, Arc<TrackedTick<EspClock>>>;
+
+// Value shared by tasks and ISR timers. It's only used in a critical section, so ISR
+// never sees it half updated, even from other core. Critical section is recursive on
+// same core: `lock()` must not be called again on same cell in `f`.
+struct IsrCell<T> {
+    critical_section: IsrCriticalSection,
+    value: UnsafeCell<T>,
 }
 
-unsafe impl Sync for GlobalDimmerManager {}
+unsafe impl<T: Send> Sync for IsrCell<T> {}
 
-static GLOBAL_DIMMER_INSTANCE: GlobalDimmerManager = GlobalDimmerManager {
-    manager: RefCell::new(None),
-};
+impl<T> IsrCell<T> {
+    fn new(value: T) -> Self {
+        Self {
+            critical_section: IsrCriticalSection::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    // Call `f` with value, in critical section. Keep `f` short and don't allocate in it:
+    // interrupts are disabled.
+    #[inline(always)]
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = self.critical_section.enter();
+
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+// What ISR timer uses on each alarm
+struct IsrState {
+    engine: Engine,
+    // Alarms of one-shot timer
+    scheduler: Scheduler,
+    // The timer that manager Triac. Set after timer creation.
+    timer: Option<EspTimer<'static>>,
+    // Next plan, swapped with plan of scheduler to never allocate in critical section
+    plan: Vec<u16>,
+}
+
+// Device added to engine. Generation tells it apart from a device added later with same
+// id.
+struct Registration {
+    generation: u32,
+    target: Arc<PowerTarget>,
+}
 
-struct GlobalDevices {
-    // List of manager devices
-    devices: RefCell<Vec<DimmerDevice>>,
+// Phase angle engine of one manager, shared with its ISR timer.
+struct SharedEngine {
+    state: IsrCell<IsrState>,
+    // Tick source of engine, to read half period
+    tick: Arc<TrackedTick<EspClock>>,
+    // How ISR timer is used
+    scheduling: Scheduling,
+    // Frequency used by timer
+    frequency: Frequency,
+    // Frequency measured at startup if frequency is auto
+    measured_frequency: Option<f32>,
+    // Detect loss of zero crossing
+    watchdog: IsrCell<MainsWatchdog>,
+    // Timer that checks watchdog. Set after timer creation.
+    watchdog_timer: Mutex<Option<EspTimer<'static>>>,
+    // Last snapshot of mains statistics, to read them from other threads
+    statistics: Mutex<MainsStatistics>,
+    // Power targets of devices. ISR timer never locks it.
+    targets: Mutex<Vec<Registration>>,
+    // Generation of next registered device
+    generation: AtomicU32,
+    // Called with new faults, out of critical section
+    on_fault: Mutex<Option<FaultCallback>>,
+    // Called when mains is lost or restored, out of critical section
+    on_mains_event: Mutex<Option<MainsCallback>>,
+    // Zero crossing pin with `ZeroCrossingMode::Interrupt`
+    interrupt_pin: Option<i32>,
+    // Wake driver task of `ZeroCrossingMode::Interrupt`
+    driver: Mutex<Option<Arc<Notifier>>>,
+    // Manager is stopped, driver task ends
+    stopped: AtomicBool,
 }
 
-unsafe impl Sync for GlobalDevices {}
+struct GlobalDimmerManager {
+    // The device manager
+    manager: Mutex<Option<DevicesDimmerManager>>,
+    // Engine of device manager, to set power when manager wait zero crossing
+    engine: OnceLock<Arc<SharedEngine>>,
+}
 
-static GLOBAL_DEVICES: GlobalDevices = GlobalDevices {
-    devices: RefCell::new(vec![]),
+static GLOBAL_DIMMER_INSTANCE: GlobalDimmerManager = GlobalDimmerManager {
+    manager: Mutex::new(None),
+    engine: OnceLock::new(),
 };
 
-/// This enum represent the frequency electricity.
-#[derive(Debug, Clone, PartialEq)]
-pub enum Frequency {
-    /// Voltage has 50Hz frequency (like Europe).
-    F50HZ,
-    /// Voltage haz 60Hz frequency (like U.K.).
-    F60HZ,
+// Lock a mutex. A panic while locked cannot break values kept by this crate.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-/// Similarly, implement `Display` for `Frequency`.
-impl fmt::Display for Frequency {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Frequency::F50HZ => write!(f, "50Hz"),
-            _ => write!(f, "60Hz"),
-        }
+/// Clock of ESP, in µs since boot.
+pub struct EspClock;
+
+impl Clock for EspClock {
+    #[inline(always)]
+    fn now(&self) -> u32 {
+        unsafe { esp_idf_sys::esp_timer_get_time() as u32 }
     }
 }
 
-/// Struct to manage power of dimmer device.
-pub struct DimmerDevice {
-    id: u8,
-    pin: OutputPin,
-    invert_power: u8,
+// Time in µs since boot of a recent timestamp of `EspClock`.
+fn esp_timestamp(timestamp: u32) -> u64 {
+    let now = esp_timer_now();
+
+    now.saturating_sub((now as u32).wrapping_sub(timestamp) as u64)
 }
 
-impl DimmerDevice {
-    /// Create new struct.
-    pub fn new(id: u8, pin: OutputPin) -> Self {
-        DimmerDevice {
-            id,
-            pin,
-            invert_power: 100,
+// Time in µs since boot, without wrap.
+#[inline(always)]
+fn esp_timer_now() -> u64 {
+    unsafe { esp_idf_sys::esp_timer_get_time() as u64 }
+}
+
+impl zc::AsyncZeroCrossingPin for InputPin {
+    async fn wait_for_rising_edge(&mut self) -> Result<(), RbdDimmerError> {
+        match PinDriver::wait_for_rising_edge(self).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(RbdDimmerError::other(String::from(
+                "Fail to wait signal on Zero Cross pin",
+            ))),
         }
     }
+}
 
-    /// Set power of device. Power is percent of time of half sinusoidal (not of power).
+impl OneShotTimer for EspTimer<'static> {
     #[inline(always)]
-    pub fn set_power(&mut self, p: u8) {
-        // It's easy to turn on triac but hard to turn off when voltage > 0.
-        // Triac automatically turn off when voltage = 0.
-        // At first time of half sinusoidal, we keep off triac and turn on after.
-        // That why, we invert power.
-        self.invert_power = 100 - p;
+    fn after(&self, delay: Duration) -> Result<(), RbdDimmerError> {
+        match EspTimer::after(self, delay) {
+            Ok(_) => Ok(()),
+            // Called in ISR timer: error must not allocate
+            Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::TimerScheduled)),
+        }
+    }
+
+    fn cancel(&self) -> Result<(), RbdDimmerError> {
+        match EspTimer::cancel(self) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::TimerCancel)),
+        }
     }
+}
+
+/// Struct to manage power of dimmer device.
+pub type DimmerDevice = phase::DimmerDevice<OutputPin>;
 
-    /// Value of tick increase by ISR interrupt. Frequency depends on frequency electricity.
+impl zc::OutputPin for OutputPin {
     #[inline(always)]
-    pub fn tick(&mut self, t: u8) -> Result<(), RbdDimmerError> {
-        // If power percent is mower, shutdown pin
-        if t >= self.invert_power {
-            match self.pin.set_high() {
-                Ok(_) => Ok(()),
-                Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::SetLow)),
-            }
-        } else {
-            match self.pin.set_low() {
-                Ok(_) => Ok(()),
-                Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::SetHigh)),
-            }
+    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
+        match PinDriver::set_high(self) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::SetHigh)),
         }
     }
 
-    /// Reset pin to low.
     #[inline(always)]
-    pub fn reset(&mut self) {
-        // In case of we have 100% of power, we never reset.
-        if self.invert_power > 0 {
-            let _ = self.pin.set_low();
+    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
+        match PinDriver::set_low(self) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(RbdDimmerError::from(RbdDimmerErrorKind::SetLow)),
         }
     }
 }
 
-unsafe impl Sync for DimmerDevice {}
-
-/// Config of device manager
+/// Config of device manager. Use `DevicesDimmerManagerConfig::builder()`.
 pub struct DevicesDimmerManagerConfig {
-    /// Pin for read zero crossing
-    pub zero_crossing_pin: InputPin,
-    /// List of devices to manage
-    pub devices: Vec<DimmerDevice>,
-    /// Frequency of network (Europe = 50Hz)
-    pub frequency: Frequency,
-    /// Step of manage power. In 50Hz, by default, power is managed
-    /// every 0.1ms. But you can multiy by step_size.
-    /// That mean is step_size = 10, power management is every 1ms and
-    /// power tick is also multiply by 10 (power step wil by 0, 10, 20, 30...)
-    pub step_size: u8,
-    /// Tick max of power management in percent.
-    /// By default, you cannot set power more than 95%.
-    pub tick_max: u8,
+    // Pin for read zero crossing
+    zero_crossing_pin: InputPin,
+    // List of devices to manage
+    devices: Vec<DimmerDevice>,
+    // Checked timing settings
+    timing: TimingConfig,
+    // What to do when an output pin fails
+    fault_policy: FaultPolicy,
+    // Called on zero crossing with new faults
+    on_fault: Option<FaultCallback>,
+    // Called when mains is lost or restored
+    on_mains_event: Option<MainsCallback>,
+    // How zero crossing pin is read
+    zero_crossing_mode: ZeroCrossingMode,
 }
 
 impl DevicesDimmerManagerConfig {
-    pub fn default(
+    /// Create a builder. By default, frequency is detected, timer is one-shot, step size
+    /// is 1, tick max is 95 and there is no zero crossing offset.
+    pub fn builder(
         zero_crossing_pin: InputPin,
         devices: Vec<DimmerDevice>,
-        frequency: Frequency,
-    ) -> Self {
-        Self {
+    ) -> DevicesDimmerManagerConfigBuilder {
+        DevicesDimmerManagerConfigBuilder {
             zero_crossing_pin,
             devices,
-            frequency,
-            step_size: 1,
-            tick_max: DEFAULT_TICK_MAX,
+            timing: TimingConfigBuilder::new(),
+            fault_policy: FaultPolicy::default(),
+            on_fault: None,
+            on_mains_event: None,
+            zero_crossing_mode: ZeroCrossingMode::default(),
         }
     }
 
-    pub fn default_50_hz(zero_crossing_pin: InputPin, devices: Vec<DimmerDevice>) -> Self {
-        Self {
-            zero_crossing_pin,
-            devices,
-            frequency: Frequency::F50HZ,
-            step_size: 1,
-            tick_max: DEFAULT_TICK_MAX,
-        }
+    /// Timing settings.
+    pub fn timing(&self) -> &TimingConfig {
+        &self.timing
     }
 
-    pub fn default_60_hz(zero_crossing_pin: InputPin, devices: Vec<DimmerDevice>) -> Self {
-        Self {
-            zero_crossing_pin,
-            devices,
-            frequency: Frequency::F60HZ,
-            step_size: 1,
-            tick_max: DEFAULT_TICK_MAX,
+    /// What to do when an output pin fails.
+    pub fn fault_policy(&self) -> FaultPolicy {
+        self.fault_policy
+    }
+
+    /// How zero crossing pin is read.
+    pub fn zero_crossing_mode(&self) -> ZeroCrossingMode {
+        self.zero_crossing_mode
+    }
+}
+
+/// Builder of config of device manager. `build()` refuses settings that cannot work.
+pub struct DevicesDimmerManagerConfigBuilder {
+    zero_crossing_pin: InputPin,
+    devices: Vec<DimmerDevice>,
+    timing: TimingConfigBuilder,
+    fault_policy: FaultPolicy,
+    on_fault: Option<FaultCallback>,
+    on_mains_event: Option<MainsCallback>,
+    zero_crossing_mode: ZeroCrossingMode,
+}
+
+impl DevicesDimmerManagerConfigBuilder {
+    /// Frequency of network (Europe = 50Hz). With `Frequency::Auto`, frequency is
+    /// measured on zero crossing pin when manager is created.
+    pub fn frequency(mut self, frequency: Frequency) -> Self {
+        self.timing = self.timing.frequency(frequency);
+        self
+    }
+
+    /// Time to measure frequency, then zero crossing offset, when they are `Auto`.
+    /// Default is 1s. Without mains, `new()` fails with `RbdDimmerErrorKind::MainsLost`.
+    pub fn detection_timeout(mut self, timeout: Duration) -> Self {
+        self.timing = self.timing.detection_timeout(timeout);
+        self
+    }
+
+    /// How ISR timer is used. By default, timer is armed only when an output changes.
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.timing = self.timing.scheduling(scheduling);
+        self
+    }
+
+    /// Step of manage power with `Scheduling::Polling`. See `TimingConfigBuilder`.
+    pub fn step_size(mut self, step_size: u8) -> Self {
+        self.timing = self.timing.step_size(step_size);
+        self
+    }
+
+    /// Tick max of power management in percent. Must be a multiple of step size.
+    pub fn tick_max(mut self, tick_max: u8) -> Self {
+        self.timing = self.timing.tick_max(tick_max);
+        self
+    }
+
+    /// Delay between true zero crossing and falling edge of zero crossing pin.
+    /// With `ZeroCrossingOffset::Auto`, delay is measured when manager is created.
+    pub fn zero_crossing_offset(mut self, offset: ZeroCrossingOffset) -> Self {
+        self.timing = self.timing.zero_crossing_offset(offset);
+        self
+    }
+
+    /// What to do when an output pin fails in ISR timer. By default, error is only
+    /// recorded.
+    pub fn fault_policy(mut self, policy: FaultPolicy) -> Self {
+        self.fault_policy = policy;
+        self
+    }
+
+    /// Callback called with id of device and error when a device faults. Callback is
+    /// called on zero crossing, out of ISR timer. With `ZeroCrossingMode::Interrupt`,
+    /// it's called by driver task of crate.
+    pub fn on_fault(mut self, callback: FaultCallback) -> Self {
+        self.on_fault = Some(callback);
+        self
+    }
+
+    /// Time without zero crossing before all devices are turned off. Default is 100ms.
+    pub fn mains_timeout(mut self, timeout: Duration) -> Self {
+        self.timing = self.timing.mains_timeout(timeout);
+        self
+    }
+
+    /// Callback called once when mains is lost and once when it's restored. Lost is
+    /// reported by timer task, restored on zero crossing like `on_fault()`.
+    pub fn on_mains_event(mut self, callback: MainsCallback) -> Self {
+        self.on_mains_event = Some(callback);
+        self
+    }
+
+    /// Zero crossing edges that arrive before `window` percent of half period are
+    /// ignored. Default is 75%, 0 disables check.
+    pub fn edge_window(mut self, window: u8) -> Self {
+        self.timing = self.timing.edge_window(window);
+        self
+    }
+
+    /// Zero crossing pulses shorter than `width` µs are ignored. Default is 100µs, 0
+    /// disables check.
+    pub fn min_pulse_width(mut self, width: u32) -> Self {
+        self.timing = self.timing.min_pulse_width(width);
+        self
+    }
+
+    /// How zero crossing pin is read. By default, application calls
+    /// `wait_zero_crossing()` in a loop. With `ZeroCrossingMode::Interrupt`, edges are
+    /// read in GPIO interrupt and handled by a driver task of crate.
+    pub fn zero_crossing_mode(mut self, mode: ZeroCrossingMode) -> Self {
+        self.zero_crossing_mode = mode;
+        self
+    }
+
+    /// Check settings and create config. Ids of devices must be unique.
+    pub fn build(self) -> Result<DevicesDimmerManagerConfig, RbdDimmerError> {
+        for (i, d) in self.devices.iter().enumerate() {
+            if self.devices[..i].iter().any(|other| other.id() == d.id()) {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::DuplicateDeviceId,
+                    format!("Device {} already exists", d.id()),
+                ));
+            }
         }
+
+        Ok(DevicesDimmerManagerConfig {
+            zero_crossing_pin: self.zero_crossing_pin,
+            devices: self.devices,
+            timing: self.timing.build()?,
+            fault_policy: self.fault_policy,
+            on_fault: self.on_fault,
+            on_mains_event: self.on_mains_event,
+            zero_crossing_mode: self.zero_crossing_mode,
+        })
     }
 }
 
-/// Manager of dimmer and timer. This is a singleton.
+/// Manager of dimmer and timer.
+///
+/// Each manager has its own zero crossing pin, timer, tick counter and devices. You can
+/// create one manager per mains feed with `DevicesDimmerManager::new()`, or use the
+/// singleton with `DevicesDimmerManager::init()` and free functions.
+///
+/// When a manager is dropped, its timers and driver task are stopped and freed, and all
+/// pins of its devices are set low. Controllers and handles left don't drive them anymore.
 pub struct DevicesDimmerManager {
     // Pin to know if Zero Crossing
     zero_crossing_pin: InputPin,
-    // The timer that manager Triac
-    esp_timer: EspTimer<'static>,
+    // Tick of device timer counter, computed from zero crossing timestamps.
+    // TICK=0 means zero crossing detected. If TICK>=TICK_MAX, nothing happen.
+    tick: Arc<TrackedTick<EspClock>>,
+    // Engine and timer shared with ISR timer
+    engine: Arc<SharedEngine>,
+    // Timer to wait zero crossing with timeout
+    timeout_timer: EspAsyncTimer,
+    // Reject spikes on zero crossing pin
+    glitch_filter: GlitchFilter,
+    // Mains quality, computed on zero crossing
+    stats: MainsStats,
+    // How zero crossing pin is read
+    zero_crossing_mode: ZeroCrossingMode,
+    // Driver task of `ZeroCrossingMode::Interrupt`, joined on drop
+    driver_task: Option<JoinHandle<()>>,
 }
 
 impl DevicesDimmerManager {
+    /// Create a new manager and start its timer. With `Frequency::Auto` or
+    /// `ZeroCrossingOffset::Auto`, return `RbdDimmerErrorKind::MainsLost` if zero crossing
+    /// pin is silent during detection timeout.
+    pub fn new(mut config: DevicesDimmerManagerConfig) -> Result<Self, RbdDimmerError> {
+        let mut measured_frequency = None;
+        let detection_timeout = config.timing.detection_timeout();
+        let mut detection_timer = match EspTaskTimerService::new().and_then(|s| s.timer_async()) {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::Other,
+                    format!("Fail to create detection timer. Error code: {}", e),
+                ))
+            }
+        };
+
+        if config.timing.frequency() == &Frequency::Auto {
+            let mut glitch_filter = config.timing.detection_glitch_filter();
+            let detector = block_on(with_timeout(
+                &mut detection_timer,
+                detection_timeout,
+                Self::detect_frequency(&mut config.zero_crossing_pin, &mut glitch_filter),
+            ))?;
+
+            config.timing = config.timing.with_detected_frequency(detector.frequency()?);
+            measured_frequency = detector.measured_frequency();
+        }
+
+        let offset = match *config.timing.zero_crossing_offset() {
+            ZeroCrossingOffset::Fixed(o) => o,
+            ZeroCrossingOffset::Auto => {
+                let mut glitch_filter = config
+                    .timing
+                    .glitch_filter()
+                    .unwrap_or_else(|| config.timing.detection_glitch_filter());
+
+                block_on(with_timeout(
+                    &mut detection_timer,
+                    detection_timeout,
+                    Self::calibrate_offset(&mut config.zero_crossing_pin, &mut glitch_filter),
+                ))?
+            }
+        };
+
+        let mut manager = match Self::initialize(config, measured_frequency, offset) {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::Other,
+                    format!("Fail to initialize timer. Error code: {}", e),
+                ))
+            }
+        };
+
+        if manager.zero_crossing_mode == ZeroCrossingMode::Interrupt {
+            if let Err(e) = manager.subscribe_zero_crossing() {
+                return Err(RbdDimmerError::new(
+                    RbdDimmerErrorKind::Other,
+                    format!(
+                        "Fail to subscribe zero crossing interrupt. Error code: {}",
+                        e
+                    ),
+                ));
+            }
+        }
+
+        Ok(manager)
+    }
+
     /// At first time, init the manager singleton.
     pub fn init(config: DevicesDimmerManagerConfig) -> Result<(), RbdDimmerError> {
-        TICK.store(config.tick_max, aOrdering::Relaxed);
+        let mut global_manager = lock(&GLOBAL_DIMMER_INSTANCE.manager);
 
-        match Self::initialize(config) {
-            Ok(d) => Ok(d),
-            Err(e) => Err(RbdDimmerError::new(
-                RbdDimmerErrorKind::Other,
-                format!("Fail to initialize timer. Error code: {}", e),
-            )),
+        if global_manager.is_some() || GLOBAL_DIMMER_INSTANCE.engine.get().is_some() {
+            return Err(RbdDimmerError::from(
+                RbdDimmerErrorKind::DimmerManagerAlreadyInit,
+            ));
         }
+
+        let manager = Self::new(config)?;
+
+        let _ = GLOBAL_DIMMER_INSTANCE.engine.set(manager.engine.clone());
+        *global_manager = Some(manager);
+
+        Ok(())
+    }
+
+    /// Set power of a device of this manager. Power is applied on next zero crossing, even
+    /// if it is set from another task or core.
+    pub fn set_power(&self, id: u8, power: u8) -> Result<(), RbdDimmerError> {
+        self.engine.set_power(id, power)
+    }
+
+    /// Set power of a device of this manager in per-mille (0 to 1000).
+    pub fn set_power_permille(&self, id: u8, power: u16) -> Result<(), RbdDimmerError> {
+        self.engine.set_power_permille(id, power)
+    }
+
+    /// Start a fade of a device to `target` during `duration`. Fade is advanced on each
+    /// zero crossing.
+    pub fn fade_to(
+        &self,
+        id: u8,
+        target: u8,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        self.engine.fade_to(id, target, duration, easing)
+    }
+
+    /// Stop fade of a device. Device keep current power.
+    pub fn cancel_fade(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.engine.cancel_fade(id)
+    }
+
+    /// Fade of a device is finished.
+    pub fn is_fade_done(&self, id: u8) -> Result<bool, RbdDimmerError> {
+        self.engine.is_fade_done(id)
+    }
+
+    /// Add a device while manager runs. Device is driven from next zero crossing.
+    /// Return `RbdDimmerErrorKind::DuplicateDeviceId` if a device has same id.
+    pub fn add_device(&self, device: DimmerDevice) -> Result<(), RbdDimmerError> {
+        self.engine.add_device(device).map(|_| ())
+    }
+
+    /// Add a device and return its handle. Device is removed when handle is dropped.
+    pub fn register(&self, device: DimmerDevice) -> Result<DimmerHandle, RbdDimmerError> {
+        DimmerHandle::register(self.engine.clone(), device)
+    }
+
+    /// Remove a device while manager runs. Its pin is set low before device is returned.
+    pub fn remove_device(&self, id: u8) -> Result<DimmerDevice, RbdDimmerError> {
+        self.engine.remove_device(id, None)
+    }
+
+    /// Power of a device in percent, after limits. During a fade, current power of fade.
+    pub fn get_power(&self, id: u8) -> Result<u8, RbdDimmerError> {
+        self.engine.with_engine(|e| e.get_power(id))
+    }
+
+    /// Snapshot of state of a device.
+    pub fn device_state(&self, id: u8) -> Result<DeviceState, RbdDimmerError> {
+        self.engine.with_engine(|e| e.device_state(id))
+    }
+
+    /// Faults of output pin of a device.
+    pub fn fault(&self, id: u8) -> Result<FaultState, RbdDimmerError> {
+        self.engine.fault(id)
+    }
+
+    /// Clear latched fault of a device. Disabled or stopped devices are driven again.
+    pub fn clear_fault(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.engine.clear_fault(id)
     }
 
     /// This function wait zero crossing. Zero crossing is low to high impulsion.
+    /// With `ZeroCrossingMode::Interrupt`, return an error: zero crossing is handled by
+    /// driver task.
     #[inline(always)]
-    fn wait_zero_crossing(&mut self) -> Result<(), RbdDimmerError> {
-        let result = block_on(self.zero_crossing_pin.wait_for_falling_edge());
+    pub fn wait_zero_crossing(&mut self) -> Result<(), RbdDimmerError> {
+        block_on(self.wait_zero_crossing_async())
+    }
+
+    /// Like `wait_zero_crossing()`, but executor can run other tasks while waiting.
+    pub async fn wait_zero_crossing_async(&mut self) -> Result<(), RbdDimmerError> {
+        self.check_task_mode()?;
+
+        let result = wait_valid_edge(
+            &mut self.zero_crossing_pin,
+            &mut self.glitch_filter,
+            self.tick.half_period(),
+        )
+        .await;
 
         match result {
-            Ok(_) => {
-                TICK.store(0, aOrdering::Relaxed);
-                Ok(())
-            }
+            Ok(timestamp) => self.zero_crossing(timestamp),
             Err(_) => Err(RbdDimmerError::other(String::from(
                 "Fail to wait signal on Zero Cross pin",
             ))),
         }
     }
 
+    /// Wait zero crossing during `timeout`. Return `RbdDimmerErrorKind::MainsLost` if no
+    /// zero crossing happens.
+    pub fn wait_zero_crossing_timeout(&mut self, timeout: Duration) -> Result<(), RbdDimmerError> {
+        block_on(self.wait_zero_crossing_timeout_async(timeout))
+    }
+
+    /// Like `wait_zero_crossing_timeout()`, but executor can run other tasks while
+    /// waiting.
+    pub async fn wait_zero_crossing_timeout_async(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), RbdDimmerError> {
+        self.check_task_mode()?;
+
+        let timestamp = with_timeout(
+            &mut self.timeout_timer,
+            timeout,
+            wait_valid_edge(
+                &mut self.zero_crossing_pin,
+                &mut self.glitch_filter,
+                self.tick.half_period(),
+            ),
+        )
+        .await?;
+
+        self.zero_crossing(timestamp)
+    }
+
+    /// Driver task: wait zero crossings until an error happens. Mains loss is not an
+    /// error, devices are off until mains is back (see `mains_timeout()` of config).
+    pub async fn run(&mut self) -> Result<(), RbdDimmerError> {
+        loop {
+            self.wait_zero_crossing_async().await?;
+        }
+    }
+
+    /// Mains statistics measured on zero crossings.
+    pub fn statistics(&self) -> MainsStatistics {
+        // Measured by driver task
+        if self.zero_crossing_mode == ZeroCrossingMode::Interrupt {
+            return self.engine.statistics().unwrap_or_default();
+        }
+
+        MainsStatistics {
+            rejected_crossings: self.glitch_filter.rejected(),
+            ..self.stats.statistics()
+        }
+    }
+
+    /// Number of zero crossing edges rejected by glitch filter.
+    pub fn rejected_crossings(&self) -> u32 {
+        self.statistics().rejected_crossings
+    }
+
+    /// How zero crossing pin is read.
+    pub fn zero_crossing_mode(&self) -> ZeroCrossingMode {
+        self.zero_crossing_mode
+    }
+
+    /// No zero crossing during mains timeout. Devices are off until mains is back.
+    pub fn is_mains_lost(&self) -> Result<bool, RbdDimmerError> {
+        self.engine.is_mains_lost()
+    }
+
+    // Zero crossing detected at `timestamp`. Error if ISR timer cannot be armed.
+    #[inline(always)]
+    fn zero_crossing(&mut self, timestamp: u64) -> Result<(), RbdDimmerError> {
+        self.tick.zero_crossing_at(timestamp as u32);
+
+        let result = self.engine.on_zero_crossing(timestamp);
+
+        // Statistics are updated after timing-critical work
+        self.stats.set_half_period(self.tick.half_period());
+        self.stats.zero_crossing(timestamp);
+        self.engine.publish_statistics(self.statistics());
+
+        result
+    }
+
+    /// Frequency used by manager. If config frequency is `Frequency::Auto`, this is the
+    /// detected frequency.
+    pub fn frequency(&self) -> Frequency {
+        self.engine.frequency.clone()
+    }
+
+    /// Frequency (in Hz) measured at startup. `None` if frequency is not `Frequency::Auto`.
+    pub fn measured_frequency(&self) -> Option<f32> {
+        self.engine.measured_frequency
+    }
+
+    /// Half period of electricity in µs, estimated from zero crossing.
+    pub fn half_period(&self) -> u32 {
+        self.tick.half_period()
+    }
+
+    /// Delay in µs between true zero crossing and zero crossing edge.
+    pub fn zero_crossing_offset(&self) -> i32 {
+        self.tick.offset()
+    }
+
     /// Stop timer
-    fn stop(&self) -> Result<bool, RbdDimmerError> {
-        match self.esp_timer.cancel() {
-            Ok(status) => Ok(status),
-            Err(e) => Err(RbdDimmerError::new(
-                RbdDimmerErrorKind::TimerCancel,
-                format!("Fail to stop timer. Error code: {}", e),
-            )),
+    pub fn stop(&self) -> Result<bool, RbdDimmerError> {
+        self.engine.stop()
+    }
+
+    /// Handle to control this manager from other tasks while it waits zero crossing.
+    pub fn controller(&self) -> DimmerController {
+        DimmerController {
+            engine: self.engine.clone(),
         }
     }
 
-    fn initialize(config: DevicesDimmerManagerConfig) -> Result<(), EspError> {
+    // `wait_zero_crossing()` cannot be used when zero crossing is handled in interrupt.
+    fn check_task_mode(&self) -> Result<(), RbdDimmerError> {
+        match self.zero_crossing_mode {
+            ZeroCrossingMode::Task => Ok(()),
+            ZeroCrossingMode::Interrupt => Err(RbdDimmerError::other(String::from(
+                "Zero crossing is handled by interrupt",
+            ))),
+        }
+    }
+
+    // Read edges of zero crossing pin in GPIO interrupt. Interrupt posts zero crossing
+    // to driver task.
+    fn subscribe_zero_crossing(&mut self) -> Result<(), EspError> {
+        let pin = self.zero_crossing_pin.pin();
+        let mailbox = Arc::new(ZeroCrossingMailbox::new());
+        let mut interrupt = ZeroCrossingInterrupt::new(self.glitch_filter.clone());
+        let handler = InterruptHandler {
+            tick: self.tick.clone(),
+            mailbox: mailbox.clone(),
+            notifier: self.spawn_driver(mailbox)?,
+        };
+
+        self.zero_crossing_pin
+            .set_interrupt_type(InterruptType::AnyEdge)?;
+
         unsafe {
-            {
-                let mut devices = GLOBAL_DEVICES.devices.borrow_mut();
+            self.zero_crossing_pin.subscribe(move || {
+                let high = esp_idf_sys::gpio_get_level(pin) != 0;
+
+                interrupt.edge(&handler, high, esp_timer_now());
+                handler.mailbox.set_rejected(interrupt.rejected_crossings());
+
+                // Interrupt is disabled after each notification
+                esp_idf_sys::gpio_intr_enable(pin);
+            })?;
+        }
+
+        self.zero_crossing_pin.enable_interrupt()
+    }
+
+    // Start driver task of `ZeroCrossingMode::Interrupt`. Return what wakes it.
+    fn spawn_driver(
+        &mut self,
+        mailbox: Arc<ZeroCrossingMailbox>,
+    ) -> Result<Arc<Notifier>, EspError> {
+        let engine = self.engine.clone();
+        let (sender, receiver) = mpsc::sync_channel(1);
+
+        // High priority, to arm timer soon after zero crossing
+        ThreadSpawnConfiguration {
+            name: Some(b"rbd_dimmer\0"),
+            stack_size: DRIVER_TASK_STACK_SIZE,
+            priority: DRIVER_TASK_PRIORITY,
+            ..Default::default()
+        }
+        .set()?;
 
-                for d in config.devices {
-                    devices.push(d);
+        let spawned = std::thread::Builder::new()
+            .stack_size(DRIVER_TASK_STACK_SIZE)
+            .spawn(move || {
+                // Notification is bound to task that creates it
+                let notification = Notification::new();
+
+                if sender.send(notification.notifier()).is_ok() {
+                    drive_interrupt(engine, mailbox, notification);
                 }
-            } // Borrom mut is release here
+            });
 
-            let step_size = config.step_size;
-            let tick_max = config.tick_max;
+        ThreadSpawnConfiguration::default().set()?;
 
-            let callback = move || {
-                let tick = TICK.load(aOrdering::Relaxed);
+        let (task, notifier) = match spawned
+            .ok()
+            .and_then(|t| receiver.recv().ok().map(|n| (t, n)))
+        {
+            Some(d) => d,
+            None => return Err(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_NO_MEM }>()),
+        };
 
-                match tick.cmp(&tick_max) {
-                    Ordering::Less => {
-                        if let Ok(mut devices) = GLOBAL_DEVICES.devices.try_borrow_mut() {
-                            for d in devices.iter_mut() {
-                                // TODO check error or not?
-                                let _ = d.tick(tick);
-                            }
-                        }
+        *lock(&self.engine.driver) = Some(notifier.clone());
+        self.driver_task = Some(task);
 
-                        TICK.store(tick + step_size, aOrdering::Relaxed);
-                    }
-                    Ordering::Greater => {}
-                    Ordering::Equal => {
-                        if let Ok(mut devices) = GLOBAL_DEVICES.devices.try_borrow_mut() {
-                            for d in devices.iter_mut() {
-                                d.reset();
-                            }
-                        }
+        Ok(notifier)
+    }
+
+    // Measure time between zero crossing edges. Glitches would give a wrong frequency.
+    async fn detect_frequency(
+        zero_crossing_pin: &mut InputPin,
+        glitch_filter: &mut GlitchFilter,
+    ) -> Result<FrequencyDetector, EspError> {
+        let mut detector = FrequencyDetector::new(DEFAULT_DETECTION_HALF_CYCLES);
+
+        loop {
+            let (_, end) = wait_valid_pulse(zero_crossing_pin, glitch_filter).await?;
+
+            if detector.edge(end) {
+                return Ok(detector);
+            }
+        }
+    }
+
+    // Measure width of zero crossing pulses.
+    async fn calibrate_offset(
+        zero_crossing_pin: &mut InputPin,
+        glitch_filter: &mut GlitchFilter,
+    ) -> Result<i32, EspError> {
+        let mut calibrator = OffsetCalibrator::new(DEFAULT_CALIBRATION_PULSES);
+
+        loop {
+            let (start, end) = wait_valid_pulse(zero_crossing_pin, glitch_filter).await?;
+
+            // Same wrapping µs as `EspClock`
+            if calibrator.pulse(start as u32, end as u32) {
+                return Ok(calibrator.offset().unwrap_or_default());
+            }
+        }
+    }
+
+    fn initialize(
+        config: DevicesDimmerManagerConfig,
+        measured_frequency: Option<f32>,
+        offset: i32,
+    ) -> Result<Self, EspError> {
+        // Frequency is always detected here
+        let half_period = config.timing.frequency().half_period().unwrap_or_default();
+        let tick = Arc::new(TrackedTick::new(EspClock, half_period));
+
+        tick.set_offset(offset);
+
+        let mut engine = PhaseAngleEngine::new(
+            tick.clone(),
+            config.timing.step_size(),
+            config.timing.tick_max(),
+        );
+
+        let mut targets = Vec::with_capacity(config.devices.len());
+
+        for (generation, d) in config.devices.into_iter().enumerate() {
+            targets.push(Registration {
+                generation: generation as u32,
+                target: d.power_target(),
+            });
+            engine.add(d);
+        }
+
+        engine.set_fault_policy(config.fault_policy);
+
+        let mains_timeout = config.timing.mains_timeout();
+
+        // Plan has at most one tick per fine tick
+        let plan_capacity =
+            config.timing.tick_max() as usize * phase::FINE_TICKS_PER_TICK as usize + 1;
+
+        let engine = Arc::new(SharedEngine {
+            state: IsrCell::new(IsrState {
+                engine,
+                scheduler: Scheduler::with_capacity(plan_capacity),
+                timer: None,
+                plan: Vec::with_capacity(plan_capacity),
+            }),
+            tick: tick.clone(),
+            scheduling: config.timing.scheduling(),
+            frequency: config.timing.frequency().clone(),
+            measured_frequency,
+            watchdog: IsrCell::new(MainsWatchdog::new(mains_timeout)),
+            watchdog_timer: Mutex::new(None),
+            statistics: Mutex::new(MainsStatistics::default()),
+            generation: AtomicU32::new(targets.len() as u32),
+            targets: Mutex::new(targets),
+            on_fault: Mutex::new(config.on_fault),
+            on_mains_event: Mutex::new(config.on_mains_event),
+            interrupt_pin: (config.zero_crossing_mode == ZeroCrossingMode::Interrupt)
+                .then(|| config.zero_crossing_pin.pin()),
+            driver: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        });
+
+        let isr_engine = engine.clone();
+
+        let callback = move || isr_engine.on_timer();
+
+        // Timer creator
+        let esp_timer_service = EspISRTimerService::new()?;
+        let esp_timer = unsafe { esp_timer_service.timer(callback)? };
+
+        if config.timing.scheduling() == Scheduling::Polling {
+            esp_timer.every(config.timing.timer_period().unwrap_or_default())?;
+        }
+
+        // One-shot timer is armed on first zero crossing
+        engine.state.lock(|s| s.timer = Some(esp_timer));
+
+        // Watchdog is checked 4 times per timeout, in timer task to call callback
+        let task_timer_service = EspTaskTimerService::new()?;
+        let watchdog_engine = engine.clone();
+        let watchdog_timer = task_timer_service.timer(move || watchdog_engine.check_mains())?;
+
+        watchdog_timer.every(mains_timeout / 4)?;
+
+        *lock(&engine.watchdog_timer) = Some(watchdog_timer);
+
+        let timeout_timer = task_timer_service.timer_async()?;
+
+        // Frequency is always detected here
+        let glitch_filter = config
+            .timing
+            .glitch_filter()
+            .unwrap_or_else(|| GlitchFilter::new(0));
+
+        Ok(Self {
+            zero_crossing_pin: config.zero_crossing_pin,
+            tick,
+            engine,
+            timeout_timer,
+            glitch_filter,
+            stats: MainsStats::new(half_period),
+            zero_crossing_mode: config.zero_crossing_mode,
+            driver_task: None,
+        })
+    }
+}
+
+impl Drop for DevicesDimmerManager {
+    // Timers and driver task hold engine. They are freed here, else engine is never freed
+    // and devices could still be driven.
+    fn drop(&mut self) {
+        let _ = self.engine.stop();
+
+        let timer = self.engine.state.lock(|s| {
+            s.engine.shutdown();
+            s.timer.take()
+        });
+
+        // Timers are deleted out of critical section
+        drop(timer);
+        drop(lock(&self.engine.watchdog_timer).take());
+
+        // Driver task ends, it's woken by `stop()`
+        if let Some(task) = self.driver_task.take() {
+            let _ = task.join();
+        }
+    }
+}
+
+impl SharedEngine {
+    // Call `f` with engine to read it. ISR timer waits end of `f`.
+    fn with_engine<R>(
+        &self,
+        f: impl FnOnce(&Engine) -> Result<R, RbdDimmerError>,
+    ) -> Result<R, RbdDimmerError> {
+        self.state.lock(|s| f(&s.engine))
+    }
+
+    // Call `f` with engine. ISR timer waits end of `f`.
+    fn with_engine_mut<R>(
+        &self,
+        f: impl FnOnce(&mut Engine) -> Result<R, RbdDimmerError>,
+    ) -> Result<R, RbdDimmerError> {
+        self.state.lock(|s| f(&mut s.engine))
+    }
+
+    // Devices are added and removed with this lock, so registrations follow engine.
+    fn targets(&self) -> MutexGuard<'_, Vec<Registration>> {
+        lock(&self.targets)
+    }
+
+    // Registration of device `id`. With `generation`, device must be this one.
+    fn find(
+        targets: &[Registration],
+        id: u8,
+        generation: Option<u32>,
+    ) -> Result<&Registration, RbdDimmerError> {
+        targets
+            .iter()
+            .find(|r| r.target.id() == id && generation.is_none_or(|g| r.generation == g))
+            .ok_or_else(|| RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound))
+    }
+
+    // Add device. Return its generation. Only a push is done in critical section: id is
+    // checked and list of devices grows before.
+    fn add_device(&self, device: DimmerDevice) -> Result<u32, RbdDimmerError> {
+        let mut targets = self.targets();
+
+        // Registrations follow devices of engine
+        if targets.iter().any(|r| r.target.id() == device.id()) {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::DuplicateDeviceId,
+                format!("Device {} already exists", device.id()),
+            ));
+        }
+
+        if self.state.lock(|s| s.engine.spare_capacity()) == 0 {
+            let mut buffer = Vec::with_capacity(targets.len() * 2 + 1);
+
+            self.state.lock(|s| s.engine.swap_devices(&mut buffer));
+        }
+
+        let target = device.power_target();
+
+        self.state.lock(|s| s.engine.add(device));
+
+        let generation = self.generation.fetch_add(1, aOrdering::Relaxed);
+
+        targets.push(Registration { generation, target });
+
+        Ok(generation)
+    }
+
+    // Remove device, of `generation` if it's set. Pin is set low before ISR timer can use
+    // engine again.
+    fn remove_device(
+        &self,
+        id: u8,
+        generation: Option<u32>,
+    ) -> Result<DimmerDevice, RbdDimmerError> {
+        let mut targets = self.targets();
+
+        Self::find(&targets, id, generation)?;
+
+        let device = self.with_engine_mut(|e| e.remove(id))?;
+
+        targets.retain(|r| r.target.id() != id);
+
+        Ok(device)
+    }
+
+    // Power target of a device, of `generation` if it's set
+    fn power_target(
+        &self,
+        id: u8,
+        generation: Option<u32>,
+    ) -> Result<Arc<PowerTarget>, RbdDimmerError> {
+        Self::find(&self.targets(), id, generation).map(|r| r.target.clone())
+    }
+
+    // Call `f` with engine if device `id` is still the one of `generation`
+    fn with_registered<R>(
+        &self,
+        id: u8,
+        generation: u32,
+        f: impl FnOnce(&Engine) -> Result<R, RbdDimmerError>,
+    ) -> Result<R, RbdDimmerError> {
+        let targets = self.targets();
+
+        Self::find(&targets, id, Some(generation))?;
+
+        self.with_engine(f)
+    }
+
+    // Set power. Power is applied by ISR on next zero crossing.
+    fn set_power(&self, id: u8, power: u8) -> Result<(), RbdDimmerError> {
+        self.set_power_permille(id, power as u16 * 10)
+    }
+
+    // Set power in per-mille. Power is applied by ISR on next zero crossing.
+    fn set_power_permille(&self, id: u8, power: u16) -> Result<(), RbdDimmerError> {
+        self.power_target(id, None)?.request(power)
+    }
+
+    // Start fade.
+    fn fade_to(
+        &self,
+        id: u8,
+        target: u8,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        let half_cycles = fade::half_cycles(duration, self.tick.half_period());
+
+        // Error message is built out of critical section
+        self.power_target(id, None)?.check(target as u16 * 10)?;
+
+        self.with_engine_mut(|e| e.fade_to(id, target, half_cycles, easing))
+    }
+
+    fn cancel_fade(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.with_engine_mut(|e| e.cancel_fade(id))
+    }
+
+    fn is_fade_done(&self, id: u8) -> Result<bool, RbdDimmerError> {
+        self.with_engine(|e| e.is_fade_done(id))
+    }
+
+    fn fault(&self, id: u8) -> Result<FaultState, RbdDimmerError> {
+        self.with_engine(|e| e.fault(id))
+    }
+
+    fn clear_fault(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.with_engine_mut(|e| e.clear_fault(id))
+    }
+
+    // Keep snapshot of statistics
+    fn publish_statistics(&self, statistics: MainsStatistics) {
+        *lock(&self.statistics) = statistics;
+    }
+
+    fn statistics(&self) -> Result<MainsStatistics, RbdDimmerError> {
+        Ok(*lock(&self.statistics))
+    }
+
+    fn is_mains_lost(&self) -> Result<bool, RbdDimmerError> {
+        self.with_engine(|e| Ok(e.is_mains_lost()))
+    }
+
+    // Feed watchdog, restore devices if mains was lost, then start new half sinusoidal.
+    fn on_zero_crossing(&self, timestamp: u64) -> Result<(), RbdDimmerError> {
+        let event = self.watchdog.lock(|w| w.zero_crossing(timestamp));
+
+        if event.is_some() {
+            self.state.lock(|s| s.engine.mains_restored());
+        }
+
+        let result = self.half_cycle();
+
+        if let Some(event) = event {
+            self.report_mains_event(event);
+        }
+
+        result
+    }
+
+    // Called by watchdog timer task. Turn off devices when mains is lost.
+    fn check_mains(&self) {
+        let now = esp_timer_now();
+
+        let event = match self.watchdog.lock(|w| w.check(now)) {
+            Some(e) => e,
+            None => return,
+        };
+
+        self.state.lock(|s| s.engine.mains_lost());
+        self.report_mains_event(event);
+    }
+
+    // Send mains event to callback, out of critical section
+    fn report_mains_event(&self, event: MainsEvent) {
+        if let Some(callback) = lock(&self.on_mains_event).as_mut() {
+            callback(event);
+        }
+    }
+
+    // Advance fades and soft-starts and arm one-shot timer for new half sinusoidal.
+    // Plan is computed in buffer kept for it, so planning doesn't allocate in critical
+    // section.
+    // If timer cannot be armed, outputs are reset and error is returned.
+    fn half_cycle(&self) -> Result<(), RbdDimmerError> {
+        let half_period = self.tick.half_period();
+        let scheduling = self.scheduling;
+
+        let result = self.state.lock(|s| {
+            s.engine.half_cycle();
+
+            if scheduling != Scheduling::OneShot {
+                return Ok(());
+            }
+
+            // Not synchronized yet
+            if self.tick.elapsed().is_none() {
+                return Ok(());
+            }
+
+            s.engine.plan_into(&mut s.plan);
+
+            let timer = match s.timer.as_ref() {
+                Some(t) => t,
+                None => return Ok(()),
+            };
+
+            // Read after planning, else every alarm is late by planning time
+            let elapsed = self.tick.elapsed().unwrap_or_default();
+            let result = s.scheduler.start(timer, &mut s.plan, half_period, elapsed);
+
+            if result.is_err() {
+                // Nothing will turn them off
+                s.engine.fire(u8::MAX);
+            }
+
+            result
+        });
+
+        self.report_faults();
+
+        result
+    }
+
+    // Send new faults to callback. Callback is called out of critical section.
+    fn report_faults(&self) {
+        let mut on_fault = lock(&self.on_fault);
+
+        let callback = match on_fault.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+
+        while let Some((id, e)) = self.state.lock(|s| s.engine.take_fault_report()) {
+            callback(id, &e);
+        }
+    }
+
+    // Called by ISR timer.
+    #[inline(always)]
+    fn on_timer(&self) {
+        self.state.lock(|s| match self.scheduling {
+            Scheduling::Polling => s.engine.advance(),
+            Scheduling::OneShot => {
+                let tick = match s.timer.as_ref() {
+                    // Read in critical section, it's stale after waiting for it
+                    Some(timer) => {
+                        let elapsed = self.tick.elapsed().unwrap_or_default();
+
+                        s.scheduler.alarm(timer, elapsed).ok().flatten()
                     }
+                    None => None,
                 };
-            };
 
-            // Timer creator
-            let esp_timer_service = EspISRTimerService::new()?;
-            let esp_timer = esp_timer_service.timer(callback)?;
+                if let Some(tick) = tick {
+                    s.engine.fire_fine(tick);
+                }
+            }
+        });
+    }
 
-            let f = match config.frequency {
-                Frequency::F50HZ => HZ_50_DURATION,
-                _ => HZ_60_DURATION,
-            };
+    // Stop zero crossing interrupt, ISR timers and driver task.
+    fn stop(&self) -> Result<bool, RbdDimmerError> {
+        if let Some(pin) = self.interrupt_pin {
+            unsafe {
+                esp_idf_sys::gpio_intr_disable(pin);
+            }
+        }
 
-            esp_timer.every(Duration::from_micros(
-                (f as u64) * (config.step_size as u64),
-            ))?;
+        self.stopped.store(true, aOrdering::Relaxed);
 
-            // Create New device manager
-            let mut manager = GLOBAL_DIMMER_INSTANCE.manager.borrow_mut();
+        if let Some(notifier) = lock(&self.driver).as_ref() {
+            unsafe {
+                notifier.notify_and_yield(NonZeroU32::MIN);
+            }
+        }
 
-            *manager = Some(Self {
-                zero_crossing_pin: config.zero_crossing_pin,
-                esp_timer,
-            });
+        if let Some(t) = lock(&self.watchdog_timer).as_ref() {
+            OneShotTimer::cancel(t)?;
+        }
+
+        self.state.lock(|s| match s.timer.as_ref() {
+            Some(timer) => {
+                s.scheduler.stop(timer)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+}
+
+// Handler of GPIO interrupt. Zero crossing is only posted to driver task.
+struct InterruptHandler {
+    tick: Arc<TrackedTick<EspClock>>,
+    mailbox: Arc<ZeroCrossingMailbox>,
+    notifier: Arc<Notifier>,
+}
+
+impl ZeroCrossingHandler for InterruptHandler {
+    #[inline(always)]
+    fn half_period(&self) -> u32 {
+        self.tick.half_period()
+    }
+
+    #[inline(always)]
+    fn zero_crossing(&self, timestamp: u64) {
+        self.mailbox.post(timestamp as u32);
+
+        unsafe {
+            self.notifier.notify_and_yield(NonZeroU32::MIN);
+        }
+    }
+}
+
+// Driver task of `ZeroCrossingMode::Interrupt`. Do out of ISR what
+// `wait_zero_crossing()` does, with timestamp posted by interrupt. Ends when manager is
+// stopped.
+fn drive_interrupt(
+    engine: Arc<SharedEngine>,
+    mailbox: Arc<ZeroCrossingMailbox>,
+    notification: Notification,
+) {
+    let mut stats = MainsStats::new(engine.tick.half_period());
+    let mut seen = 0;
+
+    while !engine.stopped.load(aOrdering::Relaxed) {
+        notification.wait(BLOCK);
+
+        let timestamp = match mailbox.take(&mut seen) {
+            Some(t) => esp_timestamp(t),
+            None => continue,
+        };
+
+        engine.tick.zero_crossing_at(timestamp as u32);
+
+        // On error, outputs are reset. Timer is armed again on next zero crossing.
+        let _ = engine.on_zero_crossing(timestamp);
+
+        // Statistics are updated after timing-critical work
+        stats.set_half_period(engine.tick.half_period());
+        stats.zero_crossing(timestamp);
+        engine.publish_statistics(MainsStatistics {
+            rejected_crossings: mailbox.rejected(),
+            ..stats.statistics()
+        });
+    }
+}
+
+// Wait a zero crossing pulse accepted by glitch filter. Zero crossing is falling edge
+// of pulse, its timestamp is returned.
+async fn wait_valid_edge(
+    zero_crossing_pin: &mut InputPin,
+    glitch_filter: &mut GlitchFilter,
+    half_period: u32,
+) -> Result<u64, EspError> {
+    // Half period tracked by PLL
+    glitch_filter.set_half_period(half_period);
+
+    let (_, end) = wait_valid_pulse(zero_crossing_pin, glitch_filter).await?;
+
+    Ok(end)
+}
+
+// Return timestamps of rising and falling edges of first pulse accepted by glitch
+// filter.
+async fn wait_valid_pulse(
+    zero_crossing_pin: &mut InputPin,
+    glitch_filter: &mut GlitchFilter,
+) -> Result<(u64, u64), EspError> {
+    loop {
+        zero_crossing_pin.wait_for_rising_edge().await?;
+
+        let start = esp_timer_now();
+
+        zero_crossing_pin.wait_for_falling_edge().await?;
 
-            Ok(())
+        let end = esp_timer_now();
+
+        if glitch_filter.edge(end, Some(end.saturating_sub(start) as u32)) {
+            return Ok((start, end));
+        }
+    }
+}
+
+// Run `future` until it ends or `timeout` expires. Timeout means no zero crossing, it's
+// `RbdDimmerErrorKind::MainsLost`.
+async fn with_timeout<T>(
+    timer: &mut EspAsyncTimer,
+    timeout: Duration,
+    future: impl Future<Output = Result<T, EspError>>,
+) -> Result<T, RbdDimmerError> {
+    let mut future = Box::pin(future);
+    let mut expired = Box::pin(timer.after(timeout));
+
+    // First of future or timeout
+    let result = poll_fn(|cx| {
+        if let Poll::Ready(r) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(r));
+        }
+
+        match expired.as_mut().poll(cx) {
+            Poll::Ready(_) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
         }
+    })
+    .await;
+
+    match result {
+        Some(Ok(r)) => Ok(r),
+        Some(Err(_)) => Err(RbdDimmerError::other(String::from(
+            "Fail to wait signal on Zero Cross pin",
+        ))),
+        None => Err(RbdDimmerError::new(
+            RbdDimmerErrorKind::MainsLost,
+            format!("No zero crossing during {:?}", timeout),
+        )),
     }
 }
 
 /// Set power of a device. The list of device is singleton.
 pub fn set_power(id: u8, power: u8) -> Result<(), RbdDimmerError> {
-    match GLOBAL_DEVICES.devices.try_borrow_mut() {
-        Ok(mut devices) => match devices.iter_mut().find(|d| d.id == id) {
-            Some(device) => {
-                device.set_power(power);
-                Ok(())
-            }
-            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
-        },
-        Err(_) => Ok(()),
+    global_engine()?.set_power(id, power)
+}
+
+/// Set power of a device in per-mille (0 to 1000). The list of device is singleton.
+pub fn set_power_permille(id: u8, power: u16) -> Result<(), RbdDimmerError> {
+    global_engine()?.set_power_permille(id, power)
+}
+
+/// Start a fade of a device to `target` during `duration`.
+pub fn fade_to(
+    id: u8,
+    target: u8,
+    duration: Duration,
+    easing: Easing,
+) -> Result<(), RbdDimmerError> {
+    global_engine()?.fade_to(id, target, duration, easing)
+}
+
+/// Stop fade of a device.
+pub fn cancel_fade(id: u8) -> Result<(), RbdDimmerError> {
+    global_engine()?.cancel_fade(id)
+}
+
+/// Fade of a device is finished.
+pub fn is_fade_done(id: u8) -> Result<bool, RbdDimmerError> {
+    global_engine()?.is_fade_done(id)
+}
+
+/// Handle of a registered device. Device is turned off and removed when handle is
+/// dropped or released, so its id can be registered again. If device is removed by id,
+/// handle doesn't control a new device with same id: it returns
+/// `RbdDimmerErrorKind::DimmerNotFound`.
+pub struct DimmerHandle {
+    // Id of device
+    id: u8,
+    // Registration of device
+    generation: u32,
+    // Engine of manager of device
+    engine: Arc<SharedEngine>,
+    // Device is already turned off and removed
+    released: bool,
+}
+
+impl DimmerHandle {
+    // Add device to engine
+    fn register(engine: Arc<SharedEngine>, device: DimmerDevice) -> Result<Self, RbdDimmerError> {
+        let id = device.id();
+        let generation = engine.add_device(device)?;
+
+        Ok(Self {
+            id,
+            generation,
+            engine,
+            released: false,
+        })
+    }
+
+    /// Id of device.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Set power of device. See `DimmerDevice::set_power()`. Power is applied on next
+    /// zero crossing.
+    pub fn set_power(&self, power: u8) -> Result<(), RbdDimmerError> {
+        self.engine
+            .power_target(self.id, Some(self.generation))?
+            .request(power as u16 * 10)
+    }
+
+    /// Power of device in percent, after limits.
+    pub fn get_power(&self) -> Result<u8, RbdDimmerError> {
+        self.engine
+            .with_registered(self.id, self.generation, |e| e.get_power(self.id))
+    }
+
+    /// Turn off device.
+    pub fn off(&self) -> Result<(), RbdDimmerError> {
+        self.set_power(0)
+    }
+
+    /// Turn off and remove device. Device is returned with its pin. On error, handle
+    /// still tries to remove device when dropped.
+    pub fn release(mut self) -> Result<DimmerDevice, RbdDimmerError> {
+        let device = self.engine.remove_device(self.id, Some(self.generation))?;
+
+        self.released = true;
+
+        Ok(device)
+    }
+}
+
+impl Drop for DimmerHandle {
+    // Release registration. If pin fails, device is kept but turned off.
+    fn drop(&mut self) {
+        if !self.released
+            && self
+                .engine
+                .remove_device(self.id, Some(self.generation))
+                .is_err()
+        {
+            let _ = self.off();
+        }
+    }
+}
+
+/// Control of a manager from other tasks, while manager waits zero crossing. Clone it
+/// for each task. See `DevicesDimmerManager::controller()`.
+#[derive(Clone)]
+pub struct DimmerController {
+    // Engine of manager
+    engine: Arc<SharedEngine>,
+}
+
+impl DimmerController {
+    /// Set power of a device. Power is applied on next zero crossing.
+    pub fn set_power(&self, id: u8, power: u8) -> Result<(), RbdDimmerError> {
+        self.engine.set_power(id, power)
+    }
+
+    /// Set power of a device in per-mille (0 to 1000).
+    pub fn set_power_permille(&self, id: u8, power: u16) -> Result<(), RbdDimmerError> {
+        self.engine.set_power_permille(id, power)
+    }
+
+    /// Start a fade of a device to `target` during `duration`.
+    pub fn fade_to(
+        &self,
+        id: u8,
+        target: u8,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        self.engine.fade_to(id, target, duration, easing)
+    }
+
+    /// Stop fade of a device. Device keep current power.
+    pub fn cancel_fade(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.engine.cancel_fade(id)
+    }
+
+    /// Fade of a device is finished.
+    pub fn is_fade_done(&self, id: u8) -> Result<bool, RbdDimmerError> {
+        self.engine.is_fade_done(id)
+    }
+
+    /// Add a device. Device is driven from next zero crossing.
+    pub fn add_device(&self, device: DimmerDevice) -> Result<(), RbdDimmerError> {
+        self.engine.add_device(device).map(|_| ())
+    }
+
+    /// Add a device and return its handle.
+    pub fn register(&self, device: DimmerDevice) -> Result<DimmerHandle, RbdDimmerError> {
+        DimmerHandle::register(self.engine.clone(), device)
+    }
+
+    /// Remove a device. Its pin is set low before device is returned.
+    pub fn remove_device(&self, id: u8) -> Result<DimmerDevice, RbdDimmerError> {
+        self.engine.remove_device(id, None)
+    }
+
+    /// Power of a device in percent, after limits.
+    pub fn get_power(&self, id: u8) -> Result<u8, RbdDimmerError> {
+        self.engine.with_engine(|e| e.get_power(id))
     }
+
+    /// Snapshot of state of a device.
+    pub fn device_state(&self, id: u8) -> Result<DeviceState, RbdDimmerError> {
+        self.engine.with_engine(|e| e.device_state(id))
+    }
+
+    /// Faults of output pin of a device.
+    pub fn fault(&self, id: u8) -> Result<FaultState, RbdDimmerError> {
+        self.engine.fault(id)
+    }
+
+    /// Clear latched fault of a device.
+    pub fn clear_fault(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.engine.clear_fault(id)
+    }
+
+    /// Mains statistics of last zero crossing.
+    pub fn statistics(&self) -> Result<MainsStatistics, RbdDimmerError> {
+        self.engine.statistics()
+    }
+
+    /// No zero crossing during mains timeout.
+    pub fn is_mains_lost(&self) -> Result<bool, RbdDimmerError> {
+        self.engine.is_mains_lost()
+    }
+
+    /// Frequency used by manager.
+    pub fn frequency(&self) -> Frequency {
+        self.engine.frequency.clone()
+    }
+
+    /// Stop manager. Waits of manager are not interrupted, but nothing is fired anymore.
+    pub fn stop(&self) -> Result<bool, RbdDimmerError> {
+        self.engine.stop()
+    }
+}
+
+// Engine of singleton.
+fn global_engine() -> Result<Arc<SharedEngine>, RbdDimmerError> {
+    GLOBAL_DIMMER_INSTANCE
+        .engine
+        .get()
+        .cloned()
+        .ok_or_else(|| RbdDimmerError::from(RbdDimmerErrorKind::DimmerManagerNotInit))
 }
 
-/// Stop manager.
+// Singleton manager taken by a task that waits zero crossing. Singleton is not locked
+// while waiting: other functions use shared engine. Manager is put back on drop, even if
+// waiting future is dropped.
+struct ManagerLease(Option<DevicesDimmerManager>);
+
+impl ManagerLease {
+    // `None` if another task has taken manager.
+    fn take() -> Result<Option<Self>, RbdDimmerError> {
+        // Engine is set with manager
+        global_engine()?;
+
+        Ok(lock(&GLOBAL_DIMMER_INSTANCE.manager)
+            .take()
+            .map(|m| Self(Some(m))))
+    }
+}
+
+impl Deref for ManagerLease {
+    type Target = DevicesDimmerManager;
+
+    fn deref(&self) -> &DevicesDimmerManager {
+        self.0.as_ref().expect("manager is put back on drop")
+    }
+}
+
+impl DerefMut for ManagerLease {
+    fn deref_mut(&mut self) -> &mut DevicesDimmerManager {
+        self.0.as_mut().expect("manager is put back on drop")
+    }
+}
+
+impl Drop for ManagerLease {
+    fn drop(&mut self) {
+        *lock(&GLOBAL_DIMMER_INSTANCE.manager) = self.0.take();
+    }
+}
+
+/// Add a device to singleton manager while it runs.
+pub fn add_device(device: DimmerDevice) -> Result<(), RbdDimmerError> {
+    global_engine()?.add_device(device).map(|_| ())
+}
+
+/// Remove a device of singleton manager while it runs. Its pin is set low before device
+/// is returned.
+pub fn remove_device(id: u8) -> Result<DimmerDevice, RbdDimmerError> {
+    global_engine()?.remove_device(id, None)
+}
+
+/// Add a device to singleton manager and return its handle.
+pub fn register(device: DimmerDevice) -> Result<DimmerHandle, RbdDimmerError> {
+    DimmerHandle::register(global_engine()?, device)
+}
+
+/// Power of a device in percent, after limits. During a fade, current power of fade.
+pub fn get_power(id: u8) -> Result<u8, RbdDimmerError> {
+    global_engine()?.with_engine(|e| e.get_power(id))
+}
+
+/// Snapshot of state of a device: requested and effective power, modes, faults.
+pub fn device_state(id: u8) -> Result<DeviceState, RbdDimmerError> {
+    global_engine()?.with_engine(|e| e.device_state(id))
+}
+
+/// Faults of output pin of a device.
+pub fn fault(id: u8) -> Result<FaultState, RbdDimmerError> {
+    global_engine()?.fault(id)
+}
+
+/// Clear latched fault of a device.
+pub fn clear_fault(id: u8) -> Result<(), RbdDimmerError> {
+    global_engine()?.clear_fault(id)
+}
+
+/// Mains statistics of singleton manager. Can be read while manager waits zero crossing.
+pub fn statistics() -> Result<MainsStatistics, RbdDimmerError> {
+    global_engine()?.statistics()
+}
+
+/// Frequency used by manager. Can be read while manager waits zero crossing.
+pub fn frequency() -> Result<Frequency, RbdDimmerError> {
+    Ok(global_engine()?.frequency.clone())
+}
+
+/// Frequency (in Hz) measured at startup. `None` if frequency is not `Frequency::Auto`.
+pub fn measured_frequency() -> Result<Option<f32>, RbdDimmerError> {
+    Ok(global_engine()?.measured_frequency)
+}
+
+/// Stop manager. Can be called while manager waits zero crossing.
 pub fn stop() -> Result<bool, RbdDimmerError> {
-    match GLOBAL_DIMMER_INSTANCE.manager.try_borrow_mut() {
-        Ok(mut manager) => match manager.as_mut() {
-            Some(d) => d.stop(),
-            None => Err(RbdDimmerError::from(
-                RbdDimmerErrorKind::DimmerManagerNotInit,
-            )),
-        },
-        Err(_) => Ok(false),
+    global_engine()?.stop()
+}
+
+/// Wait zero crossing during `timeout`. Return `RbdDimmerErrorKind::MainsLost` if no zero
+/// crossing happens. `Ok(false)` if another task waits zero crossing.
+pub fn wait_zero_crossing_timeout(timeout: Duration) -> Result<bool, RbdDimmerError> {
+    match ManagerLease::take()? {
+        Some(mut manager) => manager.wait_zero_crossing_timeout(timeout).map(|_| true),
+        None => Ok(false),
     }
 }
 
+/// No zero crossing during mains timeout.
+pub fn is_mains_lost() -> Result<bool, RbdDimmerError> {
+    global_engine()?.is_mains_lost()
+}
+
+/// Wait zero crossing of singleton manager. `Ok(false)` if another task waits zero
+/// crossing.
 pub fn wait_zero_crossing() -> Result<bool, RbdDimmerError> {
-    match GLOBAL_DIMMER_INSTANCE.manager.try_borrow_mut() {
-        Ok(mut manager) => match manager.as_mut() {
-            Some(d) => match d.wait_zero_crossing() {
-                Ok(()) => Ok(true),
-                Err(e) => Err(e),
-            },
-            None => Err(RbdDimmerError::from(
-                RbdDimmerErrorKind::DimmerManagerNotInit,
-            )),
-        },
-        Err(_) => Ok(false),
+    match ManagerLease::take()? {
+        Some(mut manager) => manager.wait_zero_crossing().map(|_| true),
+        None => Ok(false),
     }
 }
+
+/// Like `wait_zero_crossing()`, but executor can run other tasks while waiting.
+pub async fn wait_zero_crossing_async() -> Result<bool, RbdDimmerError> {
+    match ManagerLease::take()? {
+        Some(mut manager) => manager.wait_zero_crossing_async().await.map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Driver task of singleton manager. Spawn it on executor instead of a thread that loops
+/// on `wait_zero_crossing()`. Manager is used by task until it ends on error, other
+/// functions (power, `stop()`...) still work.
+pub async fn run() -> Result<(), RbdDimmerError> {
+    match ManagerLease::take()? {
+        Some(mut manager) => manager.run().await,
+        None => Err(RbdDimmerError::other(String::from(
+            "Manager is already used",
+        ))),
+    }
+}
+
+/// Spawn `run()` on an `edge-executor` executor. Task ends with error of `run()`.
+pub fn spawn<'a, const C: usize>(
+    executor: &LocalExecutor<'a, C>,
+) -> Task<Result<(), RbdDimmerError>> {
+    executor.spawn(run())
+}
@@ -0,0 +1,123 @@
+//! Power limits of a device
+//!
+//! Some loads must not be driven at any power: a lamp is not visible under few percent,
+//! a heater must be capped. Limits check power requested before it's applied.
+//!
+//! 0 always turns device off.
+use crate::error::*;
+
+#[cfg(test)]
+mod tests;
+
+/// What to do when power requested is out of limits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LimitPolicy {
+    /// Power is moved to nearest limit.
+    #[default]
+    Clamp,
+    /// Power is refused with `RbdDimmerErrorKind::PowerOutOfRange`.
+    Reject,
+}
+
+/// Minimum and maximum power of a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerLimits {
+    min: u8,
+    max: u8,
+    // Power under min turns device off
+    off_below_min: bool,
+    policy: LimitPolicy,
+}
+
+impl Default for PowerLimits {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: 100,
+            off_below_min: false,
+            policy: LimitPolicy::default(),
+        }
+    }
+}
+
+impl PowerLimits {
+    /// Create limits. `min` must be lower or equal to `max` and `max` lower or equal to
+    /// 100.
+    pub fn new(min: u8, max: u8) -> Result<Self, RbdDimmerError> {
+        if min > max || max > 100 {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::InvalidPowerLimits,
+                format!("Invalid power limits: min {}, max {}", min, max),
+            ));
+        }
+
+        Ok(Self {
+            min,
+            max,
+            ..Default::default()
+        })
+    }
+
+    /// Power under min turns device off instead of being clamped or rejected.
+    pub fn with_off_below_min(mut self, off_below_min: bool) -> Self {
+        self.off_below_min = off_below_min;
+        self
+    }
+
+    /// Set what to do with power out of limits.
+    pub fn with_policy(mut self, policy: LimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Minimum power.
+    pub fn min(&self) -> u8 {
+        self.min
+    }
+
+    /// Maximum power.
+    pub fn max(&self) -> u8 {
+        self.max
+    }
+
+    /// Check power requested with policy.
+    pub fn apply(&self, power: u8) -> Result<u8, RbdDimmerError> {
+        Ok((self.apply_permille(power as u16 * 10)? / 10) as u8)
+    }
+
+    /// Move power in limits, whatever policy.
+    pub fn clamp(&self, power: u8) -> u8 {
+        (self.clamp_permille(power as u16 * 10) / 10) as u8
+    }
+
+    /// Check power requested in per-mille with policy.
+    pub fn apply_permille(&self, power: u16) -> Result<u16, RbdDimmerError> {
+        let clamped = self.clamp_permille(power);
+
+        if clamped != power && self.policy == LimitPolicy::Reject && !self.is_off(power) {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::PowerOutOfRange,
+                format!(
+                    "Power {}‰ is out of limits (min {}%, max {}%)",
+                    power, self.min, self.max
+                ),
+            ));
+        }
+
+        Ok(clamped)
+    }
+
+    /// Move power in per-mille in limits, whatever policy.
+    pub fn clamp_permille(&self, power: u16) -> u16 {
+        if self.is_off(power) {
+            0
+        } else {
+            power.clamp(self.min as u16 * 10, self.max as u16 * 10)
+        }
+    }
+
+    // Power in per-mille turns device off
+    fn is_off(&self, power: u16) -> bool {
+        power == 0 || (self.off_below_min && power < self.min as u16 * 10)
+    }
+}
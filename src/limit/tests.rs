@@ -0,0 +1,88 @@
+use crate::limit::*;
+
+#[test]
+fn test_limits_default() {
+    let limits = PowerLimits::default();
+
+    assert_eq!(limits.apply(0).unwrap(), 0);
+    assert_eq!(limits.apply(50).unwrap(), 50);
+    assert_eq!(limits.apply(100).unwrap(), 100);
+    // Bad command
+    assert_eq!(limits.apply(200).unwrap(), 100);
+}
+
+#[test]
+fn test_limits_invalid() {
+    assert_eq!(
+        PowerLimits::new(50, 20).unwrap_err().kind,
+        RbdDimmerErrorKind::InvalidPowerLimits
+    );
+    assert_eq!(
+        PowerLimits::new(0, 101).unwrap_err().kind,
+        RbdDimmerErrorKind::InvalidPowerLimits
+    );
+}
+
+#[test]
+fn test_limits_clamp() {
+    let limits = PowerLimits::new(15, 80).unwrap();
+
+    assert_eq!(limits.min(), 15);
+    assert_eq!(limits.max(), 80);
+    assert_eq!(limits.apply(0).unwrap(), 0);
+    assert_eq!(limits.apply(5).unwrap(), 15);
+    assert_eq!(limits.apply(50).unwrap(), 50);
+    assert_eq!(limits.apply(90).unwrap(), 80);
+    assert_eq!(limits.apply(255).unwrap(), 80);
+}
+
+#[test]
+fn test_limits_off_below_min() {
+    let limits = PowerLimits::new(15, 80).unwrap().with_off_below_min(true);
+
+    assert_eq!(limits.apply(5).unwrap(), 0);
+    assert_eq!(limits.apply(15).unwrap(), 15);
+    assert_eq!(limits.apply(90).unwrap(), 80);
+}
+
+#[test]
+fn test_limits_reject() {
+    let limits = PowerLimits::new(15, 80)
+        .unwrap()
+        .with_policy(LimitPolicy::Reject);
+
+    assert_eq!(limits.apply(0).unwrap(), 0);
+    assert_eq!(limits.apply(50).unwrap(), 50);
+    assert_eq!(
+        limits.apply(5).unwrap_err().kind,
+        RbdDimmerErrorKind::PowerOutOfRange
+    );
+    assert_eq!(
+        limits.apply(90).unwrap_err().kind,
+        RbdDimmerErrorKind::PowerOutOfRange
+    );
+    // Clamp ignore policy
+    assert_eq!(limits.clamp(90), 80);
+
+    // Turn off is not an error
+    let limits = limits.with_off_below_min(true);
+
+    assert_eq!(limits.apply(5).unwrap(), 0);
+}
+
+#[test]
+fn test_limits_permille() {
+    let limits = PowerLimits::new(15, 80).unwrap().with_off_below_min(true);
+
+    assert_eq!(limits.apply_permille(155).unwrap(), 155);
+    assert_eq!(limits.apply_permille(149).unwrap(), 0);
+    assert_eq!(limits.apply_permille(805).unwrap(), 800);
+    assert_eq!(limits.clamp_permille(2000), 800);
+
+    let limits = limits.with_policy(LimitPolicy::Reject);
+
+    assert_eq!(
+        limits.apply_permille(801).unwrap_err().kind,
+        RbdDimmerErrorKind::PowerOutOfRange
+    );
+}
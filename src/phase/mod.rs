@@ -0,0 +1,926 @@
+//! Phase angle engine
+//!
+//! Phase angle algorithm run by the ISR timer. This module doesn't depend on ESP, so
+//! it can be tested on host with fake pins.
+//!
+//! Each time the timer fires, the engine reads the tick source, turns on every device
+//! whose firing tick is reached (or turns off devices in trailing-edge) and increase
+//! the tick. When tick reach `tick_max`,
+//! all devices are reset to low and nothing happen until next zero crossing.
+use crate::curve::{CalibrationTable, PowerMode};
+use crate::error::*;
+use crate::fade::{Easing, Fade, SoftStart};
+use crate::fault::{FaultCallback, FaultPolicy, FaultState};
+use crate::limit::PowerLimits;
+use crate::target::PowerTarget;
+use crate::zc::OutputPin;
+use std::sync::atomic::{AtomicU8, Ordering as aOrdering};
+use std::sync::Arc;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of fine ticks in a tick.
+pub const FINE_TICKS_PER_TICK: u16 = 10;
+/// Number of fine ticks per half sinusoidal (power is in per-mille).
+pub const FINE_TICKS: u16 = 1000;
+
+/// Source of tick. TICK=0 means zero crossing detected.
+pub trait TickSource {
+    /// Read current tick
+    fn get(&self) -> u8;
+
+    /// Set current tick. A tick from `tick_max` means not synchronized: nothing happens
+    /// until next zero crossing.
+    fn set(&self, tick: u8);
+
+    /// Called by engine after each timer call with tick read. By default, tick is a
+    /// counter increased by step size.
+    #[inline(always)]
+    fn advance(&self, tick: u8, step_size: u8) {
+        self.set(tick.saturating_add(step_size));
+    }
+
+    /// Zero crossing detected. By default, tick restart from 0.
+    #[inline(always)]
+    fn zero_crossing(&self) {
+        self.set(0);
+    }
+}
+
+impl TickSource for AtomicU8 {
+    #[inline(always)]
+    fn get(&self) -> u8 {
+        self.load(aOrdering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn set(&self, tick: u8) {
+        self.store(tick, aOrdering::Relaxed)
+    }
+}
+
+impl<T> TickSource for &T
+where
+    T: TickSource + ?Sized,
+{
+    #[inline(always)]
+    fn get(&self) -> u8 {
+        (**self).get()
+    }
+
+    #[inline(always)]
+    fn set(&self, tick: u8) {
+        (**self).set(tick)
+    }
+
+    #[inline(always)]
+    fn advance(&self, tick: u8, step_size: u8) {
+        (**self).advance(tick, step_size)
+    }
+
+    #[inline(always)]
+    fn zero_crossing(&self) {
+        (**self).zero_crossing()
+    }
+}
+
+impl<T> TickSource for Arc<T>
+where
+    T: TickSource + ?Sized,
+{
+    #[inline(always)]
+    fn get(&self) -> u8 {
+        (**self).get()
+    }
+
+    #[inline(always)]
+    fn set(&self, tick: u8) {
+        (**self).set(tick)
+    }
+
+    #[inline(always)]
+    fn advance(&self, tick: u8, step_size: u8) {
+        (**self).advance(tick, step_size)
+    }
+
+    #[inline(always)]
+    fn zero_crossing(&self) {
+        (**self).zero_crossing()
+    }
+}
+
+/// How half sinusoidal is cut.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PhaseMode {
+    /// Output is off at zero crossing and turns on at firing tick (triac).
+    #[default]
+    LeadingEdge,
+    /// Output is on at zero crossing and turns off at cut tick (MOSFET). For LED drivers
+    /// and capacitive loads.
+    TrailingEdge,
+}
+
+/// How gate of triac is driven after firing tick (leading-edge only).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GateDrive {
+    /// Gate stays high until end of half sinusoidal.
+    #[default]
+    Hold,
+    /// Gate is high during a number of ticks. Triac stays on until zero crossing, opto
+    /// LED doesn't waste current.
+    Pulse(u8),
+    /// Gate is high during `width` ticks every `period` ticks until end of half
+    /// sinusoidal. For inductive loads where triac may not latch on first pulse.
+    PulseTrain { width: u8, period: u8 },
+}
+
+impl GateDrive {
+    /// Gate is high `elapsed` ticks after firing tick.
+    #[inline(always)]
+    pub fn is_on(&self, elapsed: u8) -> bool {
+        match *self {
+            GateDrive::Hold => true,
+            GateDrive::Pulse(width) => elapsed < width.max(1),
+            GateDrive::PulseTrain { width, period } => elapsed % period.max(1) < width.max(1),
+        }
+    }
+}
+
+/// Snapshot of state of a device.
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    /// Id of device.
+    pub id: u8,
+    /// Power requested in per-mille. During a fade, target of fade.
+    pub requested_power: u16,
+    /// Power in per-mille after limits. During a fade, current power of fade.
+    pub power: u16,
+    /// Per-mille of half sinusoidal where output is on, after power mode and soft-start.
+    pub conduction: u16,
+    /// How power is converted to time.
+    pub power_mode: PowerMode,
+    /// Leading or trailing edge.
+    pub phase_mode: PhaseMode,
+    /// A fade is in progress.
+    pub fading: bool,
+    /// Faults of output pin.
+    pub fault: FaultState,
+    /// Output is turned on during half sinusoidal.
+    pub is_on: bool,
+}
+
+/// Struct to manage power of dimmer device.
+///
+/// Internally, power and time are in per-mille. Backend with fine ticks (one-shot timer)
+/// use exact firing tick. Backend with ticks of 1% (polling) dither between adjacent
+/// ticks across successive half sinusoidal.
+pub struct DimmerDevice<O>
+where
+    O: OutputPin,
+{
+    id: u8,
+    pin: O,
+    // Power requested in per-mille, before limits
+    requested: u16,
+    // Power in per-mille, after limits
+    power: u16,
+    // How power is converted to time
+    power_mode: PowerMode,
+    // Min and max power
+    limits: PowerLimits,
+    // Leading or trailing edge
+    phase_mode: PhaseMode,
+    // How gate is driven after firing tick
+    gate_drive: GateDrive,
+    // Per-mille of time of half sinusoidal for power requested
+    conduction: u16,
+    // Firing fine tick
+    invert_power: u16,
+    // Firing tick for backend of 1%, after dithering
+    dithered_power: u8,
+    // Dithering error in fine ticks, report to next half sinusoidal
+    dither_error: u16,
+    // Fade in progress
+    fade: Option<Fade>,
+    // Ramp when device is turned on
+    soft_start: Option<SoftStart>,
+    // Errors of output pin
+    fault: FaultState,
+    // Power requested by other tasks
+    target: Arc<PowerTarget>,
+}
+
+impl<O> DimmerDevice<O>
+where
+    O: OutputPin,
+{
+    /// Create new struct.
+    pub fn new(id: u8, pin: O) -> Self {
+        Self {
+            id,
+            pin,
+            requested: 0,
+            power: 0,
+            power_mode: PowerMode::default(),
+            limits: PowerLimits::default(),
+            phase_mode: PhaseMode::default(),
+            gate_drive: GateDrive::default(),
+            conduction: 0,
+            invert_power: FINE_TICKS,
+            dithered_power: 100,
+            dither_error: 0,
+            fade: None,
+            soft_start: None,
+            fault: FaultState::new(),
+            target: Arc::new(PowerTarget::new(id, PowerLimits::default())),
+        }
+    }
+
+    /// When device is turned on (power goes from 0), time of half sinusoidal ramps during
+    /// `half_cycles` half sinusoidal. Ramp is independent of fade. 0 disables soft-start.
+    pub fn set_soft_start(&mut self, half_cycles: u32) {
+        self.soft_start = match half_cycles {
+            0 => None,
+            h => Some(SoftStart::new(h)),
+        };
+        self.update_invert_power();
+    }
+
+    /// Set min and max power of device. Current power is clamped in new limits.
+    pub fn set_limits(&mut self, limits: PowerLimits) {
+        self.target.set_limits(limits.clone());
+        self.limits = limits;
+        self.apply_power(self.power);
+    }
+
+    /// Mailbox to set power from any task. Power is applied on next half sinusoidal.
+    pub fn power_target(&self) -> Arc<PowerTarget> {
+        self.target.clone()
+    }
+
+    /// Set how half sinusoidal is cut. See `PhaseMode`.
+    pub fn set_phase_mode(&mut self, mode: PhaseMode) {
+        self.phase_mode = mode;
+    }
+
+    /// Set how gate is driven. See `GateDrive`. Trailing-edge always holds output.
+    pub fn set_gate_drive(&mut self, drive: GateDrive) {
+        self.gate_drive = drive;
+    }
+
+    /// Set how power is understood. See `PowerMode`.
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.power_mode = mode;
+        self.apply_power(self.power);
+    }
+
+    /// Convert power with calibration table of load.
+    pub fn set_calibration_table(&mut self, table: CalibrationTable) {
+        self.set_power_mode(PowerMode::Calibrated(table));
+    }
+
+    /// Id of device.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Set power of device. By default, power is percent of time of half sinusoidal (not
+    /// of power). With `PowerMode::Rms`, power is percent of RMS power.
+    /// Fade in progress is cancelled.
+    ///
+    /// Power out of limits is clamped or rejected, see `PowerLimits`.
+    #[inline(always)]
+    pub fn set_power(&mut self, p: u8) -> Result<(), RbdDimmerError> {
+        self.set_power_permille(p as u16 * 10)
+    }
+
+    /// Set power of device in per-mille (0 to 1000). See `set_power()`. Request of power
+    /// target not applied yet is dropped.
+    pub fn set_power_permille(&mut self, p: u16) -> Result<(), RbdDimmerError> {
+        self.request_power(p)?;
+        self.target.take();
+
+        Ok(())
+    }
+
+    /// Start a fade from current power to `target` in `half_cycles` half sinusoidal.
+    /// `target` is checked with limits, power during fade stays in limits.
+    pub fn fade_to(
+        &mut self,
+        target: u8,
+        half_cycles: u32,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        let clamped = self.limits.apply_permille(target as u16 * 10)?;
+
+        // From exact power, a rounded percent would jump
+        self.fade = Some(Fade::new_permille(self.power, clamped, half_cycles, easing));
+        self.requested = clamped;
+        // Fade is newer than request
+        self.target.take();
+
+        Ok(())
+    }
+
+    /// Stop fade in progress. Device keep current power.
+    pub fn cancel_fade(&mut self) {
+        if self.fade.take().is_some() {
+            self.requested = self.power;
+        }
+    }
+
+    /// Power in percent after limits (rounded). During a fade, current power of fade.
+    pub fn power(&self) -> u8 {
+        ((self.power + 5) / 10) as u8
+    }
+
+    /// Snapshot of state of device.
+    pub fn state(&self) -> DeviceState {
+        let conduction = FINE_TICKS - self.invert_power;
+
+        DeviceState {
+            id: self.id,
+            requested_power: self.requested,
+            power: self.power,
+            conduction,
+            power_mode: self.power_mode.clone(),
+            phase_mode: self.phase_mode,
+            fading: self.fade.is_some(),
+            fault: self.fault.clone(),
+            is_on: conduction > 0 && !self.fault.is_disabled(),
+        }
+    }
+
+    /// No fade in progress.
+    pub fn is_fade_done(&self) -> bool {
+        self.fade.is_none()
+    }
+
+    /// Advance fade, soft-start and dithering of one half sinusoidal.
+    pub fn half_cycle(&mut self) {
+        // Report error of previous half sinusoidal
+        self.dither_error = self.dither_error + self.invert_power
+            - self.dithered_power as u16 * FINE_TICKS_PER_TICK;
+
+        // Request is already checked with limits
+        if let Some(p) = self.target.take() {
+            let _ = self.request_power(p);
+        }
+
+        if let Some(soft_start) = self.soft_start.as_mut() {
+            soft_start.step();
+        }
+
+        match self.fade.as_mut() {
+            Some(fade) => {
+                fade.step();
+
+                let power = fade.power_permille();
+
+                if fade.is_done() {
+                    self.fade = None;
+                }
+
+                self.apply_power(power);
+            }
+            None => self.update_invert_power(),
+        }
+    }
+
+    // Check power with limits, stop fade and apply power
+    fn request_power(&mut self, p: u16) -> Result<(), RbdDimmerError> {
+        let clamped = self.limits.apply_permille(p)?;
+
+        self.fade = None;
+        self.requested = p;
+        self.apply_power(clamped);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn apply_power(&mut self, p: u16) {
+        let was_off = self.conduction == 0;
+
+        self.power = self.limits.clamp_permille(p);
+        self.conduction = self.power_mode.conduction_permille(self.power);
+
+        if let Some(soft_start) = self.soft_start.as_mut() {
+            if was_off && self.conduction > 0 {
+                soft_start.restart();
+            }
+        }
+
+        self.update_invert_power();
+    }
+
+    #[inline(always)]
+    fn update_invert_power(&mut self) {
+        let conduction = match self.soft_start.as_ref() {
+            Some(soft_start) => soft_start.limit(self.conduction),
+            None => self.conduction,
+        };
+
+        // It's easy to turn on triac but hard to turn off when voltage > 0.
+        // Triac automatically turn off when voltage = 0.
+        // At first time of half sinusoidal, we keep off triac and turn on after.
+        // That why, we invert power.
+        self.invert_power = FINE_TICKS - conduction.min(FINE_TICKS);
+        self.dithered_power =
+            ((self.invert_power + self.dither_error) / FINE_TICKS_PER_TICK).min(100) as u8;
+    }
+
+    /// Value of tick increase by ISR interrupt. Frequency depends on frequency electricity.
+    /// Errors are recorded in fault state. Disabled device is not driven.
+    #[inline(always)]
+    pub fn tick(&mut self, t: u8) -> Result<(), RbdDimmerError> {
+        let on = self.is_on(t);
+
+        self.drive(on)
+    }
+
+    /// Set output for fine tick `t`. See `tick()`.
+    #[inline(always)]
+    pub fn fine_tick(&mut self, t: u16) -> Result<(), RbdDimmerError> {
+        let on = self.is_on_fine(t);
+
+        self.drive(on)
+    }
+
+    /// Faults of output pin.
+    pub fn fault(&self) -> &FaultState {
+        &self.fault
+    }
+
+    /// Clear latched fault and drive device again.
+    pub fn clear_fault(&mut self) {
+        self.fault.clear();
+    }
+
+    // Set output and record error
+    #[inline(always)]
+    fn drive(&mut self, on: bool) -> Result<(), RbdDimmerError> {
+        if self.fault.is_disabled() {
+            return Ok(());
+        }
+
+        let result = if on {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        };
+
+        if let Err(e) = &result {
+            self.fault.record(e.clone());
+        }
+
+        result
+    }
+
+    /// Output is on at tick `t`.
+    #[inline(always)]
+    pub fn is_on(&self, t: u8) -> bool {
+        self.output(
+            t as u16 * FINE_TICKS_PER_TICK,
+            self.dithered_power as u16 * FINE_TICKS_PER_TICK,
+        )
+    }
+
+    /// Output is on at fine tick `t`.
+    #[inline(always)]
+    pub fn is_on_fine(&self, t: u16) -> bool {
+        self.output(t, self.invert_power)
+    }
+
+    // Output at fine tick `t` for firing fine tick `invert_power`
+    #[inline(always)]
+    fn output(&self, t: u16, invert_power: u16) -> bool {
+        match self.phase_mode {
+            // If power percent is mower, shutdown pin
+            PhaseMode::LeadingEdge => {
+                t >= invert_power
+                    && self
+                        .gate_drive
+                        .is_on(((t - invert_power) / FINE_TICKS_PER_TICK).min(u8::MAX as u16) as u8)
+            }
+            // On since zero crossing, cut when time of power is over
+            PhaseMode::TrailingEdge => t < FINE_TICKS - invert_power,
+        }
+    }
+
+    /// Reset pin to low.
+    #[inline(always)]
+    pub fn reset(&mut self) -> Result<(), RbdDimmerError> {
+        // In case of we have 100% of power, we never reset.
+        if self.invert_power > 0 {
+            self.drive(false)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Give back pin used to turn on/off.
+    pub fn into_pin(self) -> O {
+        self.pin
+    }
+
+    #[cfg(test)]
+    /// Return pin used to turn on/off
+    pub fn pin(&self) -> &O {
+        &self.pin
+    }
+}
+
+/// The phase angle engine. Call `advance()` from timer and `zero_crossing()` when
+/// zero crossing is detected.
+pub struct PhaseAngleEngine<O, T>
+where
+    O: OutputPin,
+    T: TickSource,
+{
+    // Devices to manage
+    devices: Vec<DimmerDevice<O>>,
+    // Tick counter, shared with zero crossing detection
+    tick_source: T,
+    // Value add to tick at each timer call
+    step_size: u8,
+    // After this tick, all devices are reset
+    tick_max: u8,
+    // Devices are already reset for this half sinusoidal
+    reset_done: bool,
+    // What to do when an output pin fails
+    fault_policy: FaultPolicy,
+    // All devices are stopped by a fault
+    stopped: bool,
+    // Called on zero crossing with new faults
+    on_fault: Option<FaultCallback>,
+    // No zero crossing, devices are off
+    mains_lost: bool,
+    // Manager is dropped, devices are off for good
+    shut_down: bool,
+}
+
+impl<O, T> PhaseAngleEngine<O, T>
+where
+    O: OutputPin,
+    T: TickSource,
+{
+    /// Create a new engine. Tick is set to `tick_max` (nothing happen until first zero
+    /// crossing).
+    pub fn new(tick_source: T, step_size: u8, tick_max: u8) -> Self {
+        tick_source.set(tick_max);
+
+        Self {
+            devices: vec![],
+            tick_source,
+            step_size,
+            tick_max,
+            reset_done: false,
+            fault_policy: FaultPolicy::default(),
+            stopped: false,
+            on_fault: None,
+            mains_lost: false,
+            shut_down: false,
+        }
+    }
+
+    /// Set what to do when an output pin fails.
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+    }
+
+    /// Set callback called on zero crossing (out of ISR) with id of device and error
+    /// when a device faults.
+    pub fn set_fault_callback(&mut self, callback: FaultCallback) {
+        self.on_fault = Some(callback);
+    }
+
+    /// Faults of a device.
+    pub fn fault(&self, id: u8) -> Result<FaultState, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device.fault().clone()),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Clear latched fault of a device. With `FaultPolicy::StopAll`, devices restart
+    /// when no fault is latched.
+    pub fn clear_fault(&mut self, id: u8) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.clear_fault();
+
+        if !self.devices.iter().any(|d| d.fault.is_latched()) {
+            self.stopped = false;
+        }
+
+        Ok(())
+    }
+
+    /// All devices are stopped by a fault (`FaultPolicy::StopAll`).
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Zero crossing signal is lost. All devices are turned off and are not driven until
+    /// `mains_restored()`.
+    pub fn mains_lost(&mut self) {
+        self.mains_lost = true;
+
+        for d in self.devices.iter_mut() {
+            let _ = d.pin.set_low();
+        }
+    }
+
+    /// Zero crossing signal is back. Devices are driven again, soft-starts restart.
+    pub fn mains_restored(&mut self) {
+        self.mains_lost = false;
+
+        for d in self.devices.iter_mut() {
+            if let Some(soft_start) = d.soft_start.as_mut() {
+                soft_start.restart();
+            }
+        }
+    }
+
+    /// Zero crossing signal is lost.
+    pub fn is_mains_lost(&self) -> bool {
+        self.mains_lost
+    }
+
+    /// Turn off all devices for good, when manager is dropped. Pins are set low and
+    /// nothing drives them anymore, even after `clear_fault()` or `mains_restored()`.
+    pub fn shutdown(&mut self) {
+        self.shut_down = true;
+
+        for d in self.devices.iter_mut() {
+            let _ = d.pin.set_low();
+        }
+    }
+
+    /// Add a device.
+    pub fn add(&mut self, device: DimmerDevice<O>) {
+        self.devices.push(device);
+    }
+
+    /// Add a device. Return `RbdDimmerErrorKind::DuplicateDeviceId` if a device has same
+    /// id.
+    pub fn register(&mut self, device: DimmerDevice<O>) -> Result<(), RbdDimmerError> {
+        if self.devices.iter().any(|d| d.id == device.id) {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::DuplicateDeviceId,
+                format!("Device {} already exists", device.id),
+            ));
+        }
+
+        self.devices.push(device);
+
+        Ok(())
+    }
+
+    /// Number of devices that can be added without allocation.
+    pub fn spare_capacity(&self) -> usize {
+        self.devices.capacity() - self.devices.len()
+    }
+
+    /// Move devices to `buffer` and use it as list of devices. `buffer` gets old empty
+    /// list. Nothing is allocated if `buffer` has room for devices: list can grow out of
+    /// a critical section.
+    pub fn swap_devices(&mut self, buffer: &mut Vec<DimmerDevice<O>>) {
+        buffer.append(&mut self.devices);
+        std::mem::swap(&mut self.devices, buffer);
+    }
+
+    /// Remove a device. Its pin is set low before device is returned. If pin fails,
+    /// device is kept.
+    pub fn remove(&mut self, id: u8) -> Result<DimmerDevice<O>, RbdDimmerError> {
+        let index = match self.devices.iter().position(|d| d.id == id) {
+            Some(i) => i,
+            None => return Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        };
+
+        self.devices[index].pin.set_low()?;
+
+        Ok(self.devices.remove(index))
+    }
+
+    /// Set power of a device.
+    pub fn set_power(&mut self, id: u8, power: u8) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.set_power(power)
+    }
+
+    /// Set power of a device in per-mille.
+    pub fn set_power_permille(&mut self, id: u8, power: u16) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.set_power_permille(power)
+    }
+
+    /// Mailbox to set power of a device from any task. See `PowerTarget`.
+    pub fn power_target(&self, id: u8) -> Result<Arc<PowerTarget>, RbdDimmerError> {
+        Ok(self.device(id)?.power_target())
+    }
+
+    /// Power of a device in percent, after limits. During a fade, current power of fade.
+    pub fn get_power(&self, id: u8) -> Result<u8, RbdDimmerError> {
+        Ok(self.device(id)?.power())
+    }
+
+    /// Snapshot of state of a device. Devices stopped by fault or mains loss are off.
+    pub fn device_state(&self, id: u8) -> Result<DeviceState, RbdDimmerError> {
+        let mut state = self.device(id)?.state();
+
+        state.is_on = state.is_on && !self.stopped && !self.mains_lost;
+
+        Ok(state)
+    }
+
+    /// Start a fade of a device to `target` in `half_cycles` half sinusoidal.
+    pub fn fade_to(
+        &mut self,
+        id: u8,
+        target: u8,
+        half_cycles: u32,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.fade_to(target, half_cycles, easing)
+    }
+
+    /// Stop fade of a device.
+    pub fn cancel_fade(&mut self, id: u8) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.cancel_fade();
+        Ok(())
+    }
+
+    /// Fade of a device is finished.
+    pub fn is_fade_done(&self, id: u8) -> Result<bool, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device.is_fade_done()),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Advance fades and soft-starts of one half sinusoidal.
+    pub fn half_cycle(&mut self) {
+        for d in self.devices.iter_mut() {
+            d.half_cycle();
+        }
+
+        self.report_faults();
+    }
+
+    /// Take a new fault not sent to callback, with id of device. Use it to report faults
+    /// without callback, e.g. when engine is shared with an ISR.
+    pub fn take_fault_report(&mut self) -> Option<(u8, RbdDimmerError)> {
+        self.devices
+            .iter_mut()
+            .find_map(|d| d.fault.take_report().map(|e| (d.id, e)))
+    }
+
+    // Send new faults to callback
+    fn report_faults(&mut self) {
+        if let Some(callback) = self.on_fault.as_mut() {
+            for d in self.devices.iter_mut() {
+                if let Some(e) = d.fault.take_report() {
+                    callback(d.id, &e);
+                }
+            }
+        }
+    }
+
+    /// Zero crossing detected, restart half sinusoidal and advance fades and
+    /// soft-starts.
+    #[inline(always)]
+    pub fn zero_crossing(&mut self) {
+        self.tick_source.zero_crossing();
+        self.half_cycle();
+    }
+
+    /// Called by timer every step. Turn on devices, or reset them once tick reach
+    /// `tick_max`.
+    #[inline(always)]
+    pub fn advance(&mut self) {
+        let tick = self.tick_source.get();
+
+        if tick < self.tick_max {
+            self.fire(tick);
+            self.reset_done = false;
+            self.tick_source.advance(tick, self.step_size);
+        } else if !self.reset_done {
+            self.fire(tick);
+            self.reset_done = true;
+        }
+    }
+
+    /// Update devices for `tick`. Devices are reset if tick reach `tick_max`.
+    #[inline(always)]
+    pub fn fire(&mut self, tick: u8) {
+        if tick < self.tick_max {
+            self.drive(|d| d.tick(tick));
+        } else {
+            self.drive(|d| d.reset());
+        }
+    }
+
+    /// Update devices for fine tick `t`. Devices are reset if tick reach `tick_max`.
+    #[inline(always)]
+    pub fn fire_fine(&mut self, t: u16) {
+        if t < self.fine_tick_max() {
+            self.drive(|d| d.fine_tick(t));
+        } else {
+            self.drive(|d| d.reset());
+        }
+    }
+
+    // Call `f` on each device and apply fault policy on error
+    #[inline(always)]
+    fn drive<F>(&mut self, f: F)
+    where
+        F: Fn(&mut DimmerDevice<O>) -> Result<(), RbdDimmerError>,
+    {
+        if self.stopped || self.mains_lost || self.shut_down {
+            return;
+        }
+
+        let mut failed = false;
+
+        for d in self.devices.iter_mut() {
+            if f(d).is_err() {
+                failed = true;
+
+                if self.fault_policy == FaultPolicy::DisableDevice {
+                    d.fault.disable();
+                    let _ = d.pin.set_low();
+                }
+            }
+        }
+
+        if failed && self.fault_policy == FaultPolicy::StopAll {
+            self.stopped = true;
+
+            for d in self.devices.iter_mut() {
+                let _ = d.pin.set_low();
+            }
+        }
+    }
+
+    /// Sorted fine ticks where an output changes during half sinusoidal. Always contains
+    /// 0 and `tick_max` (reset).
+    pub fn plan(&self) -> Vec<u16> {
+        let mut plan = Vec::with_capacity(self.fine_tick_max() as usize + 1);
+
+        self.plan_into(&mut plan);
+
+        plan
+    }
+
+    /// Same as `plan()`, in an existing buffer. Nothing is allocated if buffer has
+    /// capacity of `tick_max` fine ticks + 1.
+    pub fn plan_into(&self, plan: &mut Vec<u16>) {
+        let fine_tick_max = self.fine_tick_max();
+
+        plan.clear();
+        plan.push(0);
+
+        for t in 1..fine_tick_max {
+            if self
+                .devices
+                .iter()
+                .any(|d| d.is_on_fine(t) != d.is_on_fine(t - 1))
+            {
+                plan.push(t);
+            }
+        }
+
+        plan.push(fine_tick_max);
+    }
+
+    #[inline(always)]
+    fn fine_tick_max(&self) -> u16 {
+        self.tick_max as u16 * FINE_TICKS_PER_TICK
+    }
+
+    /// Current tick.
+    pub fn tick(&self) -> u8 {
+        self.tick_source.get()
+    }
+
+    // Find device by id
+    fn device(&self, id: u8) -> Result<&DimmerDevice<O>, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    // Find device by id
+    fn device_mut(&mut self, id: u8) -> Result<&mut DimmerDevice<O>, RbdDimmerError> {
+        match self.devices.iter_mut().find(|d| d.id == id) {
+            Some(device) => Ok(device),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    #[cfg(test)]
+    /// Return devices
+    pub fn devices(&self) -> &Vec<DimmerDevice<O>> {
+        &self.devices
+    }
+}
@@ -0,0 +1,1189 @@
+use std::sync::atomic::AtomicU8;
+use std::sync::{Arc, Mutex};
+
+use crate::curve::{CalibrationTable, PowerMode};
+use crate::fade::Easing;
+use crate::fault::FaultPolicy;
+use crate::limit::{LimitPolicy, PowerLimits};
+use crate::phase::*;
+use crate::pll::TrackedTick;
+use crate::testing::{FakeClock, FakeFailPin, FakePin, PinState};
+
+///////////////////////////////////////////////////////////////////////////////
+// Good or fail struct
+enum MixedPin {
+    Good(FakePin),
+    Fail(FakeFailPin),
+}
+
+impl MixedPin {
+    pub fn pin_state(&self) -> PinState {
+        match self {
+            MixedPin::Good(p) => p.pin_state,
+            MixedPin::Fail(_) => PinState::Unknow,
+        }
+    }
+}
+
+impl OutputPin for MixedPin {
+    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
+        match self {
+            MixedPin::Good(p) => p.set_high(),
+            MixedPin::Fail(p) => p.set_high(),
+        }
+    }
+
+    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
+        match self {
+            MixedPin::Good(p) => p.set_low(),
+            MixedPin::Fail(p) => p.set_low(),
+        }
+    }
+}
+
+// Engine with a good device (id 0) and a fail device (id 1) at 50%
+fn mixed_engine(tick: &AtomicU8, policy: FaultPolicy) -> PhaseAngleEngine<MixedPin, &AtomicU8> {
+    let mut engine = PhaseAngleEngine::new(tick, 1, 95);
+
+    engine.set_fault_policy(policy);
+    engine.add(DimmerDevice::new(0, MixedPin::Good(FakePin::new())));
+    engine.add(DimmerDevice::new(1, MixedPin::Fail(FakeFailPin::new())));
+    engine.set_power(0, 50).unwrap();
+    engine.set_power(1, 50).unwrap();
+
+    engine
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Test device
+#[test]
+fn test_dimmer_device_pin_low_then_up() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power(20).unwrap();
+
+    // Pin stay low
+    match dim_device.tick(10) {
+        Ok(()) => assert_eq!(dim_device.pin().pin_state, PinState::Low),
+        Err(_) => panic!(),
+    }
+
+    // Pin go high
+    match dim_device.tick(80) {
+        Ok(()) => assert_eq!(dim_device.pin().pin_state, PinState::High),
+        Err(_) => panic!(),
+    }
+
+    dim_device.reset().unwrap();
+
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_dimmer_device_full_power_never_reset() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power(100).unwrap();
+
+    assert!(dim_device.tick(0).is_ok());
+    dim_device.reset().unwrap();
+
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_rms_power_mode() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power(10).unwrap();
+    dim_device.set_power_mode(PowerMode::Rms);
+
+    // 10% of RMS power needs 26% of time
+    assert!(dim_device.tick(73).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(74).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_calibration_table() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+    let table = CalibrationTable::from_measurements(&[(10, 0.0), (50, 30.0)], 60.0).unwrap();
+
+    dim_device.set_calibration_table(table);
+    dim_device.set_power(50).unwrap();
+
+    assert!(dim_device.tick(50).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    // Dead zone
+    dim_device.set_power(1).unwrap();
+
+    assert!(dim_device.tick(88).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(89).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_power_over_100() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    // Bad command is full power, not underflow
+    dim_device.set_power(200).unwrap();
+
+    assert!(dim_device.tick(0).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_limits() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power(100).unwrap();
+    // Current power is clamped
+    dim_device.set_limits(PowerLimits::new(20, 80).unwrap());
+
+    assert!(dim_device.tick(19).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(20).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    // Under visible threshold
+    dim_device.set_power(5).unwrap();
+
+    assert!(dim_device.tick(79).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(80).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    // Off below min
+    dim_device.set_limits(PowerLimits::new(20, 80).unwrap().with_off_below_min(true));
+    dim_device.set_power(5).unwrap();
+
+    assert!(dim_device.tick(99).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_dimmer_device_limits_reject() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_limits(
+        PowerLimits::new(0, 80)
+            .unwrap()
+            .with_policy(LimitPolicy::Reject),
+    );
+    dim_device.set_power(50).unwrap();
+
+    match dim_device.set_power(90) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::PowerOutOfRange),
+    }
+
+    match dim_device.fade_to(90, 10, Easing::Linear) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::PowerOutOfRange),
+    }
+
+    // Power is not changed
+    assert!(dim_device.tick(49).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(50).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_trailing_edge() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_phase_mode(PhaseMode::TrailingEdge);
+    dim_device.set_power(30).unwrap();
+
+    // On at zero crossing
+    assert!(dim_device.tick(0).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    assert!(dim_device.tick(29).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    // Cut
+    assert!(dim_device.tick(30).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    // Off never turns on
+    dim_device.set_power(0).unwrap();
+
+    assert!(dim_device.tick(0).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    // Full power never reset
+    dim_device.set_power(100).unwrap();
+
+    assert!(dim_device.tick(94).is_ok());
+    dim_device.reset().unwrap();
+
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_gate_drive() {
+    assert!(GateDrive::Hold.is_on(0));
+    assert!(GateDrive::Hold.is_on(90));
+
+    assert!(GateDrive::Pulse(2).is_on(0));
+    assert!(GateDrive::Pulse(2).is_on(1));
+    assert!(!GateDrive::Pulse(2).is_on(2));
+    // At least one tick
+    assert!(GateDrive::Pulse(0).is_on(0));
+    assert!(!GateDrive::Pulse(0).is_on(1));
+
+    let train = GateDrive::PulseTrain {
+        width: 2,
+        period: 5,
+    };
+
+    assert!(train.is_on(0));
+    assert!(train.is_on(1));
+    assert!(!train.is_on(2));
+    assert!(!train.is_on(4));
+    assert!(train.is_on(5));
+    assert!(train.is_on(11));
+    assert!(!train.is_on(12));
+
+    // Bad period
+    assert!(GateDrive::PulseTrain {
+        width: 1,
+        period: 0
+    }
+    .is_on(7));
+}
+
+#[test]
+fn test_dimmer_device_gate_pulse() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_gate_drive(GateDrive::Pulse(3));
+    dim_device.set_power(40).unwrap();
+
+    assert!(dim_device.tick(59).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    assert!(dim_device.tick(60).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    assert!(dim_device.tick(62).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+
+    // Triac stays on, gate is released
+    assert!(dim_device.tick(63).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::Low);
+
+    // Trailing-edge ignores gate drive
+    dim_device.set_phase_mode(PhaseMode::TrailingEdge);
+
+    assert!(dim_device.tick(30).is_ok());
+    assert_eq!(dim_device.pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_gate_pulse_train() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_gate_drive(GateDrive::PulseTrain {
+        width: 1,
+        period: 4,
+    });
+    dim_device.set_power(50).unwrap();
+
+    let states: Vec<PinState> = (50..59)
+        .map(|t| {
+            dim_device.tick(t).unwrap();
+            dim_device.pin().pin_state
+        })
+        .collect();
+
+    assert_eq!(
+        states,
+        vec![
+            PinState::High,
+            PinState::Low,
+            PinState::Low,
+            PinState::Low,
+            PinState::High,
+            PinState::Low,
+            PinState::Low,
+            PinState::Low,
+            PinState::High
+        ]
+    );
+}
+
+#[test]
+fn test_dimmer_device_power_permille() {
+    let fake_pin = FakePin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power_permille(125).unwrap();
+
+    // Exact with fine ticks
+    assert!(!dim_device.is_on_fine(874));
+    assert!(dim_device.is_on_fine(875));
+
+    // Dithering between 87 and 88 with ticks
+    let mut fire_ticks = vec![];
+
+    for _ in 0..4 {
+        fire_ticks.push((80..100).find(|t| dim_device.is_on(*t)).unwrap());
+        dim_device.half_cycle();
+    }
+
+    assert_eq!(fire_ticks, vec![87, 88, 87, 88]);
+
+    // 1.2%: firing tick is 98.8 on average
+    dim_device.set_power_permille(12).unwrap();
+
+    let mut fire_ticks = vec![];
+
+    for _ in 0..5 {
+        fire_ticks.push((90..100).find(|t| dim_device.is_on(*t)).unwrap());
+        dim_device.half_cycle();
+    }
+
+    assert_eq!(fire_ticks.iter().map(|t| *t as u32).sum::<u32>(), 494);
+}
+
+#[test]
+fn test_dimmer_device_fail() {
+    let fake_pin = FakeFailPin::new();
+    let mut dim_device = DimmerDevice::new(0, fake_pin);
+
+    dim_device.set_power(20).unwrap();
+
+    // Pin go low
+    match dim_device.tick(10) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::SetLow),
+    }
+
+    // Pin go high
+    match dim_device.tick(80) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::SetHigh),
+    }
+
+    // First error is latched
+    assert_eq!(dim_device.fault().count(), 2);
+    assert_eq!(
+        dim_device.fault().error().unwrap().kind,
+        RbdDimmerErrorKind::SetLow
+    );
+
+    dim_device.clear_fault();
+
+    assert!(!dim_device.fault().is_latched());
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Test engine
+#[test]
+fn test_engine_wait_zero_crossing() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+
+    // Tick is set to tick max, nothing happen
+    assert_eq!(engine.tick(), 95);
+
+    for _ in 0..10 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.tick(), 95);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_engine_fire_then_reset() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.add(DimmerDevice::new(1, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+    engine.set_power(1, 10).unwrap();
+
+    engine.zero_crossing();
+
+    for _ in 0..50 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.tick(), 50);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::Low);
+
+    // Tick 50
+    engine.advance();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::Low);
+
+    while engine.tick() < 95 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::High);
+
+    // Tick max
+    engine.advance();
+
+    assert_eq!(engine.tick(), 95);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_engine_step_size() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 10, 90);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 30).unwrap();
+
+    engine.zero_crossing();
+
+    for _ in 0..7 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.tick(), 70);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.advance();
+
+    assert_eq!(engine.tick(), 80);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_reset_when_tick_jump_over_tick_max() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 10, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+
+    engine.zero_crossing();
+
+    while engine.tick() < 95 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.tick(), 100);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    engine.advance();
+
+    assert_eq!(engine.tick(), 100);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_engine_zero_crossing_restart() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+
+    engine.zero_crossing();
+
+    for _ in 0..60 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    engine.zero_crossing();
+    engine.advance();
+
+    assert_eq!(engine.tick(), 1);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+}
+
+#[test]
+fn test_engine_wait_first_zero_crossing() {
+    let clock = FakeClock::new(1_000_000);
+    let tick = TrackedTick::new(&clock, 10_000);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+
+    // No phase before first zero crossing, output is never turned on
+    for i in 0..300 {
+        clock.set(1_000_000 + i * 100);
+        engine.advance();
+
+        assert_ne!(engine.devices()[0].pin().pin_state, PinState::High);
+    }
+
+    engine.zero_crossing();
+    clock.advance(6_000);
+    engine.advance();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_fade() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.set_power(0, 10).unwrap();
+    engine.fade_to(0, 50, 4, Easing::Linear).unwrap();
+
+    assert!(!engine.is_fade_done(0).unwrap());
+
+    // 20%
+    engine.zero_crossing();
+
+    for _ in 0..80 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.advance();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    for _ in 0..3 {
+        engine.zero_crossing();
+    }
+
+    assert!(engine.is_fade_done(0).unwrap());
+
+    // 50%
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_dimmer_device_fade_permille() {
+    let mut dim_device = DimmerDevice::new(0, FakePin::new());
+
+    dim_device.set_limits(PowerLimits::new(0, 80).unwrap());
+    dim_device.set_power_permille(126).unwrap();
+    dim_device.fade_to(100, 10, Easing::Linear).unwrap();
+
+    // Target is clamped, fade starts from 12.6%
+    assert_eq!(dim_device.state().requested_power, 800);
+    assert_eq!(dim_device.state().power, 126);
+
+    dim_device.half_cycle();
+
+    assert_eq!(dim_device.state().power, 193);
+}
+
+#[test]
+fn test_engine_cancel_fade() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.add(DimmerDevice::new(1, FakePin::new()));
+    engine.fade_to(0, 100, 10, Easing::EaseInOut).unwrap();
+    engine.fade_to(1, 100, 10, Easing::Linear).unwrap();
+
+    engine.zero_crossing();
+    engine.cancel_fade(0).unwrap();
+    // Set power cancel fade
+    engine.set_power(1, 20).unwrap();
+
+    assert!(engine.is_fade_done(0).unwrap());
+    assert!(engine.is_fade_done(1).unwrap());
+
+    for _ in 0..10 {
+        engine.zero_crossing();
+    }
+
+    // Device 0 keep 3%, device 1 keep 20%
+    for _ in 0..81 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::High);
+
+    match engine.is_fade_done(2) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_engine_trailing_edge() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_phase_mode(PhaseMode::TrailingEdge);
+    engine.add(device);
+    engine.add(DimmerDevice::new(1, FakePin::new()));
+    engine.set_power(0, 40).unwrap();
+    engine.set_power(1, 40).unwrap();
+
+    engine.zero_crossing();
+    engine.advance();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::Low);
+
+    for _ in 0..60 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_soft_start() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_soft_start(4);
+    engine.add(device);
+
+    // Turn on: 80% is limited to 20% on first half sinusoidal
+    engine.set_power(0, 80).unwrap();
+
+    let mut fire_ticks = vec![];
+
+    for _ in 0..5 {
+        engine.zero_crossing();
+
+        loop {
+            engine.advance();
+
+            if engine.devices()[0].pin().pin_state == PinState::High {
+                break;
+            }
+        }
+
+        fire_ticks.push(engine.tick() - 1);
+    }
+
+    assert_eq!(fire_ticks, vec![80, 60, 40, 20, 20]);
+
+    // Power change without turning off, no ramp
+    engine.set_power(0, 50).unwrap();
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_soft_start_restart_after_off() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_soft_start(2);
+    device.set_power(50).unwrap();
+    engine.add(device);
+
+    for _ in 0..4 {
+        engine.zero_crossing();
+    }
+
+    engine.set_power(0, 0).unwrap();
+    engine.zero_crossing();
+    engine.set_power(0, 50).unwrap();
+
+    // 25% on first half sinusoidal
+    engine.zero_crossing();
+
+    for _ in 0..75 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.advance();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_device_not_found() {
+    let tick = AtomicU8::new(0);
+    let mut engine: PhaseAngleEngine<FakePin, &AtomicU8> = PhaseAngleEngine::new(&tick, 1, 95);
+
+    match engine.set_power(3, 50) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_engine_fault_ignore() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::Ignore);
+
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state(), PinState::High);
+    assert_eq!(engine.fault(0).unwrap().count(), 0);
+    assert_eq!(engine.fault(1).unwrap().count(), 51);
+    assert!(!engine.fault(1).unwrap().is_disabled());
+    assert!(!engine.is_stopped());
+}
+
+#[test]
+fn test_engine_fault_disable_device() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::DisableDevice);
+
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    // Other device continues
+    assert_eq!(engine.devices()[0].pin().pin_state(), PinState::High);
+
+    let fault = engine.fault(1).unwrap();
+
+    // Device is not driven anymore
+    assert_eq!(fault.count(), 1);
+    assert!(fault.is_disabled());
+    assert_eq!(fault.error().unwrap().kind, RbdDimmerErrorKind::SetLow);
+
+    engine.clear_fault(1).unwrap();
+
+    assert!(!engine.fault(1).unwrap().is_disabled());
+}
+
+#[test]
+fn test_engine_fault_stop_all() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::StopAll);
+
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert!(engine.is_stopped());
+    assert_eq!(engine.devices()[0].pin().pin_state(), PinState::Low);
+    assert_eq!(engine.fault(0).unwrap().count(), 0);
+
+    // Restart when fault is cleared
+    engine.clear_fault(1).unwrap();
+
+    assert!(!engine.is_stopped());
+
+    match engine.fault(3) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_engine_fault_callback() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::Ignore);
+    let faults = Arc::new(Mutex::new(vec![]));
+    let callback_faults = faults.clone();
+
+    engine.set_fault_callback(Box::new(move |id, e| {
+        callback_faults.lock().unwrap().push((id, e.kind.clone()));
+    }));
+
+    engine.zero_crossing();
+
+    for _ in 0..10 {
+        engine.advance();
+    }
+
+    // Reported on zero crossing
+    assert!(faults.lock().unwrap().is_empty());
+
+    engine.zero_crossing();
+
+    for _ in 0..10 {
+        engine.advance();
+    }
+
+    engine.zero_crossing();
+
+    // Reported once
+    assert_eq!(
+        *faults.lock().unwrap(),
+        vec![(1, RbdDimmerErrorKind::SetLow)]
+    );
+}
+
+#[test]
+fn test_engine_take_fault_report() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::Ignore);
+
+    engine.zero_crossing();
+
+    for _ in 0..10 {
+        engine.advance();
+    }
+
+    // Without callback, fault is kept until taken
+    engine.zero_crossing();
+
+    let (id, e) = engine.take_fault_report().unwrap();
+
+    assert_eq!(id, 1);
+    assert_eq!(e.kind, RbdDimmerErrorKind::SetLow);
+    assert!(engine.take_fault_report().is_none());
+}
+
+#[test]
+fn test_engine_mains_lost() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_soft_start(2);
+    device.set_power(50).unwrap();
+    engine.add(device);
+
+    for _ in 0..2 {
+        engine.zero_crossing();
+    }
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    // Turn off and not driven anymore
+    engine.mains_lost();
+
+    assert!(engine.is_mains_lost());
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.fire(60);
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    // Soft-start restarts
+    engine.mains_restored();
+    engine.zero_crossing();
+
+    assert!(!engine.is_mains_lost());
+
+    engine.fire(60);
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.zero_crossing();
+    engine.fire(60);
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_swap_devices() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.add(DimmerDevice::new(1, FakePin::new()));
+
+    let mut buffer = Vec::with_capacity(8);
+
+    engine.swap_devices(&mut buffer);
+
+    // Devices kept, old list given back
+    assert_eq!(engine.spare_capacity(), 6);
+    assert_eq!(engine.devices()[1].id(), 1);
+    assert!(buffer.is_empty());
+
+    engine.add(DimmerDevice::new(2, FakePin::new()));
+
+    assert_eq!(engine.spare_capacity(), 5);
+}
+
+#[test]
+fn test_engine_shutdown() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_power(50).unwrap();
+    engine.add(device);
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    // All pins are low and stay low
+    engine.shutdown();
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+
+    engine.mains_restored();
+    engine.clear_fault(0).unwrap();
+    engine.zero_crossing();
+
+    for _ in 0..95 {
+        engine.advance();
+        assert_eq!(engine.devices()[0].pin().pin_state, PinState::Low);
+    }
+}
+
+#[test]
+fn test_engine_remove_device() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine.add(DimmerDevice::new(0, FakePin::new()));
+    engine.add(DimmerDevice::new(1, FakePin::new()));
+    engine.set_power(0, 50).unwrap();
+    engine.set_power(1, 50).unwrap();
+
+    engine.zero_crossing();
+
+    for _ in 0..51 {
+        engine.advance();
+    }
+
+    // Pin is low when device is given back
+    let device = engine.remove(1).unwrap();
+
+    assert_eq!(device.id(), 1);
+    assert_eq!(device.into_pin().pin_state, PinState::Low);
+    assert_eq!(engine.devices().len(), 1);
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+
+    match engine.remove(1) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+
+    // Add device while engine runs
+    engine.add(DimmerDevice::new(2, FakePin::new()));
+    engine.set_power(2, 80).unwrap();
+    engine.zero_crossing();
+
+    for _ in 0..21 {
+        engine.advance();
+    }
+
+    assert_eq!(engine.devices()[1].pin().pin_state, PinState::High);
+}
+
+#[test]
+fn test_engine_remove_device_fail() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::Ignore);
+
+    // Pin cannot be set low, device is kept
+    match engine.remove(1) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::SetLow),
+    }
+
+    assert_eq!(engine.devices().len(), 2);
+}
+
+#[test]
+fn test_device_state() {
+    let mut dim_device = DimmerDevice::new(3, FakePin::new());
+
+    dim_device.set_limits(PowerLimits::new(0, 80).unwrap());
+    dim_device.set_power_mode(PowerMode::Rms);
+    dim_device.set_power(90).unwrap();
+
+    assert_eq!(dim_device.power(), 80);
+
+    let state = dim_device.state();
+
+    assert_eq!(state.id, 3);
+    assert_eq!(state.requested_power, 900);
+    assert_eq!(state.power, 800);
+    // RMS 80% needs less than 80% of time
+    assert!(state.conduction > 0 && state.conduction < 800);
+    assert_eq!(state.power_mode, PowerMode::Rms);
+    assert_eq!(state.phase_mode, PhaseMode::LeadingEdge);
+    assert!(!state.fading);
+    assert!(!state.fault.is_latched());
+    assert!(state.is_on);
+
+    // During fade, target is requested
+    dim_device.fade_to(20, 4, Easing::Linear).unwrap();
+    dim_device.half_cycle();
+
+    let state = dim_device.state();
+
+    assert_eq!(state.requested_power, 200);
+    assert_eq!(state.power, 650);
+    assert!(state.fading);
+
+    // Device keeps current power
+    dim_device.cancel_fade();
+
+    assert_eq!(dim_device.state().requested_power, 650);
+
+    dim_device.set_power(0).unwrap();
+
+    assert!(!dim_device.state().is_on);
+}
+
+#[test]
+fn test_engine_device_state() {
+    let tick = AtomicU8::new(0);
+    let mut engine = mixed_engine(&tick, FaultPolicy::StopAll);
+
+    assert_eq!(engine.get_power(0).unwrap(), 50);
+    assert!(engine.device_state(0).unwrap().is_on);
+
+    engine.zero_crossing();
+    engine.advance();
+
+    // Stopped by fault of device 1
+    let state = engine.device_state(0).unwrap();
+
+    assert_eq!(state.power, 500);
+    assert!(!state.is_on);
+    assert!(engine.device_state(1).unwrap().fault.is_latched());
+
+    match engine.get_power(5) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_engine_register_duplicate_id() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+
+    engine
+        .register(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+    engine
+        .register(DimmerDevice::new(1, FakePin::new()))
+        .unwrap();
+
+    match engine.register(DimmerDevice::new(0, FakePin::new())) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DuplicateDeviceId),
+    }
+
+    assert_eq!(engine.devices().len(), 2);
+}
+
+#[test]
+fn test_power_target_applied_on_half_cycle() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut device = DimmerDevice::new(0, FakePin::new());
+
+    device.set_limits(PowerLimits::new(0, 80).unwrap());
+    engine.add(device);
+
+    let target = engine.power_target(0).unwrap();
+
+    // Clamped by device
+    target.request(900).unwrap();
+
+    assert_eq!(engine.get_power(0).unwrap(), 0);
+
+    engine.zero_crossing();
+
+    assert_eq!(engine.get_power(0).unwrap(), 80);
+
+    let state = engine.device_state(0).unwrap();
+
+    assert_eq!(state.requested_power, 900);
+    assert!(!target.is_pending());
+
+    // Request cancels fade
+    engine.fade_to(0, 10, 100, Easing::Linear).unwrap();
+    target.request(500).unwrap();
+    engine.zero_crossing();
+
+    assert!(engine.is_fade_done(0).unwrap());
+    assert_eq!(engine.get_power(0).unwrap(), 50);
+
+    // Newer fade wins
+    target.request(200).unwrap();
+    engine.fade_to(0, 10, 100, Easing::Linear).unwrap();
+    engine.zero_crossing();
+
+    assert!(!engine.is_fade_done(0).unwrap());
+}
+
+#[test]
+fn test_power_target_follows_limits() {
+    let mut device = DimmerDevice::new(0, FakePin::new());
+    let target = device.power_target();
+
+    device.set_limits(
+        PowerLimits::new(0, 80)
+            .unwrap()
+            .with_policy(LimitPolicy::Reject),
+    );
+
+    // Target given before limits is same target
+    assert!(Arc::ptr_eq(&target, &device.power_target()));
+
+    match target.request(900) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::PowerOutOfRange),
+    }
+
+    target.request(500).unwrap();
+    device.half_cycle();
+
+    assert_eq!(device.power(), 50);
+}
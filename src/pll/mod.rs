@@ -0,0 +1,399 @@
+//! Software PLL
+//!
+//! Track the half period of electricity from zero crossing timestamps. Real grids
+//! drift (49.8Hz to 50.2Hz, and more with a generator), so instead of counting timer
+//! calls, tick is computed from time elapsed since the last zero crossing:
+//! there are always exactly 100 ticks per half sinusoidal.
+//!
+//! The tracker predicts the next zero crossing. If one or two zero crossings are
+//! missed (noise on zero crossing pin), tick continue with the predicted half period
+//! (flywheel).
+//!
+//! Zero crossing circuit doesn't report the true zero: the edge is offset of few
+//! hundred µs. Timestamps are compensated with an offset, that can be measured from
+//! the width of the zero crossing pulse (true zero is in the middle of the pulse).
+//!
+//! Timestamps are in µs on `u32` and can wrap.
+use crate::phase::TickSource;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering as aOrdering};
+use std::sync::Mutex;
+
+#[cfg(test)]
+mod tests;
+
+/// Number of ticks per half sinusoidal.
+pub const TICKS_PER_HALF_PERIOD: u8 = 100;
+/// Default number of zero crossing that can be missed before lost synchronization.
+pub const DEFAULT_MAX_MISSED: u8 = 2;
+// Period is stored in fixed point with 4 fractional bits (1/16 µs)
+const FRACTION_BITS: u32 = 4;
+// Frequency correction is 1/8 of phase error
+const FREQUENCY_GAIN_SHIFT: u32 = 3;
+// Phase correction is 1/2 of phase error
+const PHASE_GAIN_SHIFT: u32 = 1;
+// Period can move of 1/10 of nominal period (45Hz to 55Hz in 50Hz)
+const LOCK_RANGE_DIVIDER: u32 = 10;
+// Longest zero crossing pulse in µs. Longer pulse means an edge is missed.
+const MAX_PULSE_WIDTH: u32 = 2_000;
+
+/// Monotonic clock in µs.
+pub trait Clock {
+    /// Current time in µs. Value can wrap.
+    fn now(&self) -> u32;
+}
+
+/// Delay between true zero crossing and zero crossing edge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZeroCrossingOffset {
+    /// Offset in µs. Positive if edge happens after true zero crossing.
+    Fixed(i32),
+    /// Offset is half of the zero crossing pulse width, measured at startup.
+    Auto,
+}
+
+/// Time of true zero crossing for an edge at `timestamp` with `offset` in µs.
+#[inline(always)]
+pub fn compensate(timestamp: u32, offset: i32) -> u32 {
+    timestamp.wrapping_sub(offset as u32)
+}
+
+/// Compute tick at `now` for a half sinusoidal started at `reference`.
+///
+/// Return `None` if more than `max_missed` zero crossing are missed.
+#[inline(always)]
+pub fn phase_tick(reference: u32, half_period: u32, max_missed: u8, now: u32) -> Option<u8> {
+    if half_period == 0 {
+        return None;
+    }
+
+    let elapsed = now.wrapping_sub(reference) as i32;
+
+    // Zero crossing predicted in few µs, end of previous half sinusoidal
+    if elapsed < 0 {
+        return Some(TICKS_PER_HALF_PERIOD);
+    }
+
+    let elapsed = elapsed as u32;
+
+    if elapsed / half_period > max_missed as u32 {
+        return None;
+    }
+
+    let phase = elapsed % half_period;
+
+    Some((phase as u64 * TICKS_PER_HALF_PERIOD as u64 / half_period as u64) as u8)
+}
+
+/// Estimate half period and phase of electricity.
+pub struct PeriodTracker {
+    // Nominal half period in µs
+    nominal: u32,
+    // Estimated half period in fixed point
+    period: u32,
+    // Time of last zero crossing (measured, corrected or predicted)
+    reference: Option<u32>,
+    // Number of zero crossing can be missed
+    max_missed: u8,
+}
+
+impl PeriodTracker {
+    /// Create a tracker for nominal half period in µs (10000 for 50Hz).
+    pub fn new(nominal: u32) -> Self {
+        Self::with_max_missed(nominal, DEFAULT_MAX_MISSED)
+    }
+
+    /// Create a tracker that accept `max_missed` missing zero crossing.
+    pub fn with_max_missed(nominal: u32, max_missed: u8) -> Self {
+        Self {
+            nominal,
+            period: nominal << FRACTION_BITS,
+            reference: None,
+            max_missed,
+        }
+    }
+
+    /// A zero crossing is detected at `timestamp`. Return `false` if zero crossing is
+    /// ignored because it's too early.
+    pub fn crossing(&mut self, timestamp: u32) -> bool {
+        let reference = match self.reference {
+            Some(r) => r,
+            None => {
+                self.reference = Some(timestamp);
+                return true;
+            }
+        };
+
+        let period = self.period as i64;
+        let elapsed = (timestamp.wrapping_sub(reference) as i32 as i64) << FRACTION_BITS;
+
+        // Number of half periods since reference
+        let n = (elapsed + period / 2) / period;
+
+        if n <= 0 {
+            return false;
+        }
+
+        if n > self.max_missed as i64 + 1 {
+            // Too many zero crossing are missed, restart from this one
+            self.reference = Some(timestamp);
+            return true;
+        }
+
+        let predicted = n * period;
+        let error = elapsed - predicted;
+
+        let min = (self.nominal - self.nominal / LOCK_RANGE_DIVIDER) << FRACTION_BITS;
+        let max = (self.nominal + self.nominal / LOCK_RANGE_DIVIDER) << FRACTION_BITS;
+        let correction = (error / n) >> FREQUENCY_GAIN_SHIFT;
+
+        self.period = (period + correction).clamp(min as i64, max as i64) as u32;
+
+        let phase = (predicted + (error >> PHASE_GAIN_SHIFT)) >> FRACTION_BITS;
+
+        self.reference = Some(reference.wrapping_add(phase as u32));
+
+        true
+    }
+
+    /// Estimated half period in µs.
+    pub fn half_period(&self) -> u32 {
+        (self.period + (1 << (FRACTION_BITS - 1))) >> FRACTION_BITS
+    }
+
+    /// Time of last zero crossing.
+    pub fn reference(&self) -> Option<u32> {
+        self.reference
+    }
+
+    /// Predicted time of next zero crossing.
+    pub fn next_crossing(&self) -> Option<u32> {
+        self.reference.map(|r| r.wrapping_add(self.half_period()))
+    }
+
+    /// Set phase so that tick at `now` is `tick`. If `tick` is out of half sinusoidal,
+    /// wait next zero crossing.
+    pub fn set_tick(&mut self, now: u32, tick: u8) {
+        if tick >= TICKS_PER_HALF_PERIOD {
+            self.reference = None;
+        } else {
+            let shift = self.half_period() as u64 * tick as u64 / TICKS_PER_HALF_PERIOD as u64;
+
+            self.reference = Some(now.wrapping_sub(shift as u32));
+        }
+    }
+
+    /// Forget phase and wait next zero crossing.
+    pub fn reset(&mut self) {
+        self.reference = None;
+    }
+
+    /// Tick at `now`. `None` if not synchronized.
+    pub fn tick(&self, now: u32) -> Option<u8> {
+        self.reference
+            .and_then(|r| phase_tick(r, self.half_period(), self.max_missed, now))
+    }
+}
+
+/// Measure offset of zero crossing edge from width of zero crossing pulses.
+pub struct OffsetCalibrator {
+    // Number of pulses to measure
+    pulses: u8,
+    // Sum of pulses width
+    total: u32,
+    // Number of measured pulses
+    count: u8,
+}
+
+impl OffsetCalibrator {
+    /// Create new calibrator that measure `pulses` pulses.
+    pub fn new(pulses: u8) -> Self {
+        Self {
+            pulses: pulses.max(1),
+            total: 0,
+            count: 0,
+        }
+    }
+
+    /// A pulse start at `start` and finish at `end`. Return `true` when measure is
+    /// finished. Too long pulses are ignored.
+    pub fn pulse(&mut self, start: u32, end: u32) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        let width = end.wrapping_sub(start);
+
+        if width <= MAX_PULSE_WIDTH {
+            self.total += width;
+            self.count += 1;
+        }
+
+        self.is_done()
+    }
+
+    /// Enough pulses are measured.
+    pub fn is_done(&self) -> bool {
+        self.count >= self.pulses
+    }
+
+    /// Average width of pulses in µs.
+    pub fn pulse_width(&self) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some(self.total / self.count as u32)
+    }
+
+    /// Offset of edge at end of pulse. True zero crossing is in the middle of pulse.
+    pub fn offset(&self) -> Option<i32> {
+        self.pulse_width().map(|w| (w / 2) as i32)
+    }
+}
+
+/// Tick source computed by a `PeriodTracker`.
+///
+/// Tracker is updated on zero crossing. ISR timer only read atomics.
+pub struct TrackedTick<C>
+where
+    C: Clock,
+{
+    clock: C,
+    // Tracker, updated on zero crossing
+    tracker: Mutex<PeriodTracker>,
+    // Copy of tracker for ISR
+    reference: AtomicU32,
+    half_period: AtomicU32,
+    synchronized: AtomicBool,
+    max_missed: u8,
+    // Offset of zero crossing edge in µs
+    offset: AtomicI32,
+}
+
+impl<C> TrackedTick<C>
+where
+    C: Clock,
+{
+    /// Create new tick source for nominal half period in µs.
+    pub fn new(clock: C, nominal: u32) -> Self {
+        Self {
+            clock,
+            tracker: Mutex::new(PeriodTracker::new(nominal)),
+            reference: AtomicU32::new(0),
+            half_period: AtomicU32::new(nominal),
+            synchronized: AtomicBool::new(false),
+            max_missed: DEFAULT_MAX_MISSED,
+            offset: AtomicI32::new(0),
+        }
+    }
+
+    /// Set offset in µs of zero crossing edge. Positive if edge happens after true zero
+    /// crossing.
+    pub fn set_offset(&self, offset: i32) {
+        self.offset.store(offset, aOrdering::Relaxed);
+    }
+
+    /// Offset in µs of zero crossing edge.
+    pub fn offset(&self) -> i32 {
+        self.offset.load(aOrdering::Relaxed)
+    }
+
+    /// Estimated half period in µs.
+    pub fn half_period(&self) -> u32 {
+        self.half_period.load(aOrdering::Relaxed)
+    }
+
+    /// Predicted time of next zero crossing.
+    pub fn next_crossing(&self) -> Option<u32> {
+        if self.synchronized.load(aOrdering::Relaxed) {
+            Some(
+                self.reference
+                    .load(aOrdering::Relaxed)
+                    .wrapping_add(self.half_period()),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Time in µs since last true zero crossing. Negative if true zero crossing is not
+    /// reached yet (edge before zero). `None` if not synchronized.
+    pub fn elapsed(&self) -> Option<i32> {
+        if self.synchronized.load(aOrdering::Acquire) {
+            Some(
+                self.clock
+                    .now()
+                    .wrapping_sub(self.reference.load(aOrdering::Relaxed)) as i32,
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Zero crossing edge detected at `timestamp` (time of `Clock`). Edge offset is
+    /// removed before it's given to tracker.
+    pub fn zero_crossing_at(&self, timestamp: u32) {
+        let timestamp = compensate(timestamp, self.offset());
+
+        if let Ok(mut tracker) = self.tracker.lock() {
+            if tracker.crossing(timestamp) {
+                self.publish(&tracker);
+            }
+        }
+    }
+
+    // Copy tracker state for ISR
+    fn publish(&self, tracker: &PeriodTracker) {
+        match tracker.reference() {
+            Some(r) => {
+                self.half_period
+                    .store(tracker.half_period(), aOrdering::Relaxed);
+                self.reference.store(r, aOrdering::Relaxed);
+                self.synchronized.store(true, aOrdering::Release);
+            }
+            None => self.synchronized.store(false, aOrdering::Release),
+        }
+    }
+}
+
+impl<C> TickSource for TrackedTick<C>
+where
+    C: Clock,
+{
+    #[inline(always)]
+    fn get(&self) -> u8 {
+        if !self.synchronized.load(aOrdering::Acquire) {
+            return u8::MAX;
+        }
+
+        phase_tick(
+            self.reference.load(aOrdering::Relaxed),
+            self.half_period.load(aOrdering::Relaxed),
+            self.max_missed,
+            self.clock.now(),
+        )
+        .unwrap_or(u8::MAX)
+    }
+
+    // Tick follows time, it's never made up: 0 is a zero crossing now, any other tick
+    // (e.g. `tick_max` set by engine at start) waits next zero crossing.
+    fn set(&self, tick: u8) {
+        if let Ok(mut tracker) = self.tracker.lock() {
+            if tick == 0 {
+                tracker.set_tick(self.clock.now(), 0);
+            } else {
+                tracker.reset();
+            }
+
+            self.publish(&tracker);
+        }
+    }
+
+    // Tick is computed from time
+    #[inline(always)]
+    fn advance(&self, _tick: u8, _step_size: u8) {}
+
+    fn zero_crossing(&self) {
+        self.zero_crossing_at(self.clock.now());
+    }
+}
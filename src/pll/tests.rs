@@ -0,0 +1,265 @@
+use crate::phase::TickSource;
+use crate::pll::*;
+use crate::testing::FakeClock;
+
+// Send `count` zero crossing every `half_period` from `start`. Return time of last one.
+fn feed(tracker: &mut PeriodTracker, start: u32, half_period: u32, count: u32) -> u32 {
+    let mut timestamp = start;
+
+    for _ in 0..count {
+        assert!(tracker.crossing(timestamp));
+        timestamp = timestamp.wrapping_add(half_period);
+    }
+
+    timestamp.wrapping_sub(half_period)
+}
+
+#[test]
+fn test_phase_tick() {
+    assert_eq!(phase_tick(1_000, 10_000, 2, 1_000), Some(0));
+    assert_eq!(phase_tick(1_000, 10_000, 2, 6_000), Some(50));
+    assert_eq!(phase_tick(1_000, 10_000, 2, 10_999), Some(99));
+    // Flywheel
+    assert_eq!(phase_tick(1_000, 10_000, 2, 11_000), Some(0));
+    assert_eq!(phase_tick(1_000, 10_000, 2, 26_000), Some(50));
+    // Lost
+    assert_eq!(phase_tick(1_000, 10_000, 2, 31_000), None);
+    // Zero crossing is predicted in the future
+    assert_eq!(phase_tick(1_000, 10_000, 2, 900), Some(100));
+    // Timestamp wrap
+    assert_eq!(phase_tick(u32::MAX - 999, 10_000, 2, 4_000), Some(50));
+}
+
+#[test]
+fn test_tracker_not_synchronized() {
+    let tracker = PeriodTracker::new(10_000);
+
+    assert_eq!(tracker.tick(5_000), None);
+    assert_eq!(tracker.next_crossing(), None);
+}
+
+#[test]
+fn test_tracker_stable_50_hz() {
+    let mut tracker = PeriodTracker::new(10_000);
+    let last = feed(&mut tracker, 0, 10_000, 50);
+
+    assert_eq!(tracker.half_period(), 10_000);
+    assert_eq!(tracker.reference(), Some(last));
+    assert_eq!(tracker.next_crossing(), Some(last + 10_000));
+    assert_eq!(tracker.tick(last + 2_500), Some(25));
+}
+
+#[test]
+fn test_tracker_follow_drift() {
+    let mut tracker = PeriodTracker::new(10_000);
+
+    // 50.2Hz
+    let last = feed(&mut tracker, 0, 9_960, 200);
+
+    assert!(tracker.half_period().abs_diff(9_960) <= 1);
+    assert!(tracker.reference().unwrap().abs_diff(last) <= 2);
+
+    // Generator at 47Hz
+    let last = feed(&mut tracker, last + 10_638, 10_638, 200);
+
+    assert!(tracker.half_period().abs_diff(10_638) <= 1);
+    assert!(tracker.reference().unwrap().abs_diff(last) <= 2);
+    assert_eq!(tracker.tick(last + 5_319), Some(50));
+}
+
+#[test]
+fn test_tracker_lock_range() {
+    let mut tracker = PeriodTracker::new(10_000);
+
+    // 38Hz is out of range
+    feed(&mut tracker, 0, 13_000, 100);
+
+    assert_eq!(tracker.half_period(), 11_000);
+}
+
+#[test]
+fn test_tracker_missed_crossing() {
+    let mut tracker = PeriodTracker::new(10_000);
+    let last = feed(&mut tracker, 0, 10_000, 10);
+
+    // Two zero crossing missed, tick continue
+    assert_eq!(tracker.tick(last + 15_000), Some(50));
+    assert_eq!(tracker.tick(last + 27_000), Some(70));
+
+    assert!(tracker.crossing(last + 30_000));
+
+    assert_eq!(tracker.half_period(), 10_000);
+    assert_eq!(tracker.reference(), Some(last + 30_000));
+}
+
+#[test]
+fn test_tracker_lost() {
+    let mut tracker = PeriodTracker::new(10_000);
+    let last = feed(&mut tracker, 0, 10_000, 10);
+
+    assert_eq!(tracker.tick(last + 35_000), None);
+
+    // Restart on next zero crossing
+    assert!(tracker.crossing(last + 52_000));
+
+    assert_eq!(tracker.reference(), Some(last + 52_000));
+    assert_eq!(tracker.half_period(), 10_000);
+}
+
+#[test]
+fn test_tracker_ignore_early_crossing() {
+    let mut tracker = PeriodTracker::new(10_000);
+    let last = feed(&mut tracker, 0, 10_000, 10);
+
+    assert!(!tracker.crossing(last + 3_000));
+    assert_eq!(tracker.reference(), Some(last));
+}
+
+#[test]
+fn test_tracker_set_tick() {
+    let mut tracker = PeriodTracker::new(10_000);
+
+    tracker.set_tick(50_000, 20);
+
+    assert_eq!(tracker.reference(), Some(48_000));
+
+    tracker.set_tick(50_000, 100);
+
+    assert_eq!(tracker.reference(), None);
+}
+
+#[test]
+fn test_tracked_tick() {
+    let clock = FakeClock::new(1_000);
+    let tick = TrackedTick::new(&clock, 10_000);
+
+    // Wait first zero crossing
+    assert_eq!(tick.get(), u8::MAX);
+
+    tick.zero_crossing();
+
+    assert_eq!(tick.get(), 0);
+
+    // Advance does nothing, tick follow time
+    tick.advance(0, 1);
+    clock.set(4_000);
+
+    assert_eq!(tick.get(), 30);
+
+    clock.set(11_000);
+    tick.zero_crossing();
+    clock.set(13_000);
+
+    assert_eq!(tick.get(), 20);
+    assert_eq!(tick.half_period(), 10_000);
+    assert_eq!(tick.next_crossing(), Some(21_000));
+
+    // Zero crossing pin is lost
+    clock.set(60_000);
+
+    assert_eq!(tick.get(), u8::MAX);
+
+    tick.set(100);
+
+    assert_eq!(tick.next_crossing(), None);
+}
+
+#[test]
+fn test_tracked_tick_set() {
+    let clock = FakeClock::new(50_000);
+    let tick = TrackedTick::new(&clock, 10_000);
+
+    // Tick is never made up from a tick in half sinusoidal
+    tick.set(95);
+
+    assert_eq!(tick.get(), u8::MAX);
+    assert_eq!(tick.elapsed(), None);
+
+    tick.set(0);
+
+    assert_eq!(tick.elapsed(), Some(0));
+
+    tick.set(20);
+
+    assert_eq!(tick.next_crossing(), None);
+}
+
+#[test]
+fn test_tracked_tick_edge_timestamp() {
+    let clock = FakeClock::new(10_000);
+    let tick = TrackedTick::new(&clock, 10_000);
+
+    tick.set_offset(300);
+    tick.zero_crossing_at(10_300);
+
+    // Edge handled late, phase comes from its timestamp
+    clock.set(20_800);
+    tick.zero_crossing_at(20_300);
+
+    assert_eq!(tick.elapsed(), Some(800));
+    assert_eq!(tick.next_crossing(), Some(30_000));
+}
+
+#[test]
+fn test_compensate() {
+    assert_eq!(compensate(10_000, 300), 9_700);
+    assert_eq!(compensate(10_000, -300), 10_300);
+    assert_eq!(compensate(100, 300), u32::MAX - 199);
+    // Edge 300µs after true zero, tick at edge is already 3
+    assert_eq!(
+        phase_tick(compensate(10_000, 300), 10_000, 2, 10_000),
+        Some(3)
+    );
+    // Edge 300µs before true zero, nothing until true zero
+    assert_eq!(
+        phase_tick(compensate(10_000, -300), 10_000, 2, 10_000),
+        Some(100)
+    );
+    assert_eq!(
+        phase_tick(compensate(10_000, -300), 10_000, 2, 10_300),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_offset_calibrator() {
+    let mut calibrator = OffsetCalibrator::new(3);
+
+    assert_eq!(calibrator.offset(), None);
+
+    assert!(!calibrator.pulse(0, 580));
+    // Falling edge missed
+    assert!(!calibrator.pulse(10_000, 20_600));
+    assert!(!calibrator.pulse(20_000, 20_600));
+    assert!(calibrator.pulse(u32::MAX - 299, 320));
+
+    assert_eq!(calibrator.pulse_width(), Some(600));
+    assert_eq!(calibrator.offset(), Some(300));
+}
+
+#[test]
+fn test_tracked_tick_offset() {
+    let clock = FakeClock::new(10_300);
+    let tick = TrackedTick::new(&clock, 10_000);
+
+    tick.set_offset(300);
+
+    assert_eq!(tick.offset(), 300);
+
+    tick.zero_crossing();
+
+    assert_eq!(tick.get(), 3);
+    assert_eq!(tick.elapsed(), Some(300));
+    assert_eq!(tick.next_crossing(), Some(20_000));
+
+    clock.set(15_000);
+
+    assert_eq!(tick.get(), 50);
+
+    // Edge before true zero crossing
+    tick.set_offset(-200);
+    clock.set(19_800);
+    tick.zero_crossing();
+
+    assert_eq!(tick.elapsed(), Some(-200));
+}
@@ -0,0 +1,214 @@
+//! One-shot scheduling
+//!
+//! Instead of calling engine every tick with a periodic timer, scheduler computes on
+//! each zero crossing the sorted fine ticks where an output changes (see
+//! `PhaseAngleEngine::plan()`) and arms a one-shot timer only for those instants.
+//! Power is applied with resolution of fine tick (0.1%).
+//!
+//! If zero crossing is missed, plan is repeated on predicted zero crossing (flywheel),
+//! like PLL predicts ticks (see `pll` module).
+//!
+//! Timer is abstract. On ESP, `EspTimer` is used. On host, `SimulatedTimer` records
+//! alarms so scheduler can be tested.
+use crate::error::*;
+use crate::phase::FINE_TICKS;
+use crate::pll::DEFAULT_MAX_MISSED;
+use std::cell::Cell;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// How timer calls the phase angle engine.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scheduling {
+    /// Timer fires every tick (0.1ms in 50Hz) and engine checks all devices.
+    Polling,
+    /// Timer fires only when an output changes.
+    #[default]
+    OneShot,
+}
+
+/// Timer that calls its callback once.
+pub trait OneShotTimer {
+    /// Call callback once after `delay`. Previous alarm is cancelled.
+    fn after(&self, delay: Duration) -> Result<(), RbdDimmerError>;
+
+    /// Cancel alarm.
+    fn cancel(&self) -> Result<(), RbdDimmerError>;
+}
+
+/// Time in µs after zero crossing of a fine tick, for half sinusoidal of `half_period`
+/// µs.
+#[inline(always)]
+pub fn fine_tick_time(tick: u16, half_period: u32) -> u32 {
+    (half_period as u64 * tick as u64 / FINE_TICKS as u64) as u32
+}
+
+/// Arm one-shot timer for each tick of plan.
+pub struct Scheduler {
+    // Fine ticks of current half sinusoidal
+    plan: Vec<u16>,
+    // Index of next tick in plan
+    next: usize,
+    // Half period in µs
+    half_period: u32,
+    // Number of predicted zero crossing since last one
+    cycle: u32,
+    // Number of zero crossing can be predicted
+    max_missed: u8,
+    // Scheduler is not stopped
+    running: bool,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    /// Create new scheduler.
+    pub fn new() -> Self {
+        Self {
+            plan: vec![],
+            next: 0,
+            half_period: 0,
+            cycle: 0,
+            max_missed: DEFAULT_MAX_MISSED,
+            running: true,
+        }
+    }
+
+    /// Create new scheduler. Plans up to `capacity` ticks are swapped without any
+    /// allocation (see `start()`).
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            plan: Vec::with_capacity(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// Zero crossing detected `elapsed` µs ago. Replace plan and arm timer for first
+    /// tick. Plan is swapped with previous one, so caller can reuse its buffer and
+    /// nothing is allocated or freed.
+    pub fn start<T>(
+        &mut self,
+        timer: &T,
+        plan: &mut Vec<u16>,
+        half_period: u32,
+        elapsed: i32,
+    ) -> Result<(), RbdDimmerError>
+    where
+        T: OneShotTimer,
+    {
+        if !self.running {
+            return Ok(());
+        }
+
+        std::mem::swap(&mut self.plan, plan);
+        self.next = 0;
+        self.cycle = 0;
+        self.half_period = half_period;
+
+        self.arm(timer, elapsed)
+    }
+
+    /// Timer fired `elapsed` µs after zero crossing. Return fine tick to apply and arm
+    /// timer for next one. At end of plan, timer is armed for first tick of predicted
+    /// half sinusoidal, if zero crossing is missed.
+    pub fn alarm<T>(&mut self, timer: &T, elapsed: i32) -> Result<Option<u16>, RbdDimmerError>
+    where
+        T: OneShotTimer,
+    {
+        let tick = match self.next_tick() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        self.next += 1;
+
+        if self.next == self.plan.len() && self.cycle < self.max_missed as u32 {
+            // Replaced by plan of next zero crossing if it happens
+            self.next = 0;
+            self.cycle += 1;
+        }
+
+        self.arm(timer, elapsed)?;
+
+        Ok(Some(tick))
+    }
+
+    /// Next fine tick to apply.
+    pub fn next_tick(&self) -> Option<u16> {
+        if self.running {
+            self.plan.get(self.next).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Stop scheduler and cancel timer. Zero crossing doesn't arm timer anymore.
+    pub fn stop<T>(&mut self, timer: &T) -> Result<(), RbdDimmerError>
+    where
+        T: OneShotTimer,
+    {
+        self.running = false;
+        self.plan.clear();
+        self.next = 0;
+
+        timer.cancel()
+    }
+
+    // Arm timer for next tick
+    fn arm<T>(&self, timer: &T, elapsed: i32) -> Result<(), RbdDimmerError>
+    where
+        T: OneShotTimer,
+    {
+        match self.next_tick() {
+            Some(tick) => {
+                let time = self.cycle as i64 * self.half_period as i64
+                    + fine_tick_time(tick, self.half_period) as i64;
+                let delay = (time - elapsed as i64).max(0);
+
+                timer.after(Duration::from_micros(delay as u64))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Timer for host tests. Alarm is recorded and never fires by itself.
+#[derive(Debug, Default)]
+pub struct SimulatedTimer {
+    alarm: Cell<Option<Duration>>,
+}
+
+impl SimulatedTimer {
+    /// Create new timer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay of armed alarm.
+    pub fn alarm(&self) -> Option<Duration> {
+        self.alarm.get()
+    }
+
+    /// Fire armed alarm. Return its delay.
+    pub fn fire(&self) -> Option<Duration> {
+        self.alarm.take()
+    }
+}
+
+impl OneShotTimer for SimulatedTimer {
+    fn after(&self, delay: Duration) -> Result<(), RbdDimmerError> {
+        self.alarm.set(Some(delay));
+        Ok(())
+    }
+
+    fn cancel(&self) -> Result<(), RbdDimmerError> {
+        self.alarm.set(None);
+        Ok(())
+    }
+}
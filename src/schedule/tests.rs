@@ -0,0 +1,273 @@
+use std::sync::atomic::AtomicU8;
+
+use crate::phase::*;
+use crate::schedule::*;
+use crate::testing::{FakePin, PinState};
+
+// Run one half sinusoidal, until reset. Return time and tick of each alarm.
+fn run<T>(
+    engine: &mut PhaseAngleEngine<FakePin, T>,
+    scheduler: &mut Scheduler,
+    timer: &SimulatedTimer,
+    half_period: u32,
+    elapsed: i32,
+) -> Vec<(i32, u16)>
+where
+    T: TickSource,
+{
+    let mut elapsed = elapsed;
+    let mut alarms = vec![];
+    let mut plan = engine.plan();
+    let reset = *plan.last().unwrap();
+
+    scheduler
+        .start(timer, &mut plan, half_period, elapsed)
+        .unwrap();
+
+    while let Some(delay) = timer.fire() {
+        elapsed += delay.as_micros() as i32;
+
+        let tick = scheduler.alarm(timer, elapsed).unwrap().unwrap();
+
+        engine.fire_fine(tick);
+        alarms.push((elapsed, tick));
+
+        if tick == reset {
+            break;
+        }
+    }
+
+    alarms
+}
+
+fn device(id: u8, power: u8) -> DimmerDevice<FakePin> {
+    let mut d = DimmerDevice::new(id, FakePin::new());
+
+    d.set_power(power).unwrap();
+
+    d
+}
+
+#[test]
+fn test_fine_tick_time() {
+    assert_eq!(fine_tick_time(0, 10_000), 0);
+    assert_eq!(fine_tick_time(250, 10_000), 2_500);
+    assert_eq!(fine_tick_time(125, 10_000), 1_250);
+    assert_eq!(fine_tick_time(950, 8_333), 7_916);
+}
+
+#[test]
+fn test_scheduler_alarms() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let mut trailing = device(1, 30);
+    let mut pulse = device(2, 20);
+
+    trailing.set_phase_mode(PhaseMode::TrailingEdge);
+    pulse.set_gate_drive(GateDrive::Pulse(2));
+
+    engine.add(device(0, 50));
+    engine.add(trailing);
+    engine.add(pulse);
+
+    assert_eq!(engine.plan(), vec![0, 300, 500, 800, 820, 950]);
+
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    let alarms = run(&mut engine, &mut scheduler, &timer, 10_000, 0);
+
+    assert_eq!(
+        alarms,
+        vec![
+            (0, 0),
+            (3_000, 300),
+            (5_000, 500),
+            (8_000, 800),
+            (8_200, 820),
+            (9_500, 950)
+        ]
+    );
+    // Predicted zero crossing
+    assert_eq!(timer.alarm(), Some(Duration::from_micros(500)));
+    assert_eq!(scheduler.next_tick(), Some(0));
+}
+
+#[test]
+fn test_scheduler_outputs() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    engine.add(device(0, 40));
+
+    scheduler
+        .start(&timer, &mut engine.plan(), 10_000, 0)
+        .unwrap();
+
+    // Zero crossing
+    timer.fire();
+    engine.fire_fine(scheduler.alarm(&timer, 0).unwrap().unwrap());
+
+    assert_ne!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(timer.alarm(), Some(Duration::from_micros(6_000)));
+
+    // Firing
+    timer.fire();
+    engine.fire_fine(scheduler.alarm(&timer, 6_000).unwrap().unwrap());
+
+    assert_eq!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(timer.alarm(), Some(Duration::from_micros(3_500)));
+
+    // Reset
+    timer.fire();
+    engine.fire_fine(scheduler.alarm(&timer, 9_500).unwrap().unwrap());
+
+    assert_ne!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(timer.alarm(), Some(Duration::from_micros(500)));
+}
+
+#[test]
+fn test_scheduler_flywheel() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+    let mut elapsed = 0;
+    let mut alarms = vec![];
+
+    engine.add(device(0, 40));
+
+    scheduler
+        .start(&timer, &mut engine.plan(), 10_000, 0)
+        .unwrap();
+
+    // Zero crossing is lost, plan is repeated on 2 predicted zero crossing
+    while let Some(delay) = timer.fire() {
+        elapsed += delay.as_micros() as i32;
+
+        let tick = scheduler.alarm(&timer, elapsed).unwrap().unwrap();
+
+        engine.fire_fine(tick);
+        alarms.push((elapsed, tick));
+    }
+
+    assert_eq!(
+        alarms,
+        vec![
+            (0, 0),
+            (6_000, 600),
+            (9_500, 950),
+            (10_000, 0),
+            (16_000, 600),
+            (19_500, 950),
+            (20_000, 0),
+            (26_000, 600),
+            (29_500, 950)
+        ]
+    );
+    assert_ne!(engine.devices()[0].pin().pin_state, PinState::High);
+    assert_eq!(scheduler.next_tick(), None);
+}
+
+#[test]
+fn test_scheduler_flywheel_replaced() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    engine.add(device(0, 40));
+
+    run(&mut engine, &mut scheduler, &timer, 10_000, 0);
+
+    // Zero crossing happens 50µs before predicted one
+    let alarms = run(&mut engine, &mut scheduler, &timer, 10_000, 50);
+
+    assert_eq!(alarms, vec![(50, 0), (6_000, 600), (9_500, 950)]);
+}
+
+#[test]
+fn test_scheduler_late_and_offset() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    engine.add(device(0, 90));
+
+    // Zero crossing handled late, first alarm now
+    let alarms = run(&mut engine, &mut scheduler, &timer, 10_000, 1_500);
+
+    assert_eq!(alarms, vec![(1_500, 0), (1_500, 100), (9_500, 950)]);
+
+    // Edge before true zero crossing
+    let alarms = run(&mut engine, &mut scheduler, &timer, 10_000, -300);
+
+    assert_eq!(alarms, vec![(0, 0), (1_000, 100), (9_500, 950)]);
+}
+
+#[test]
+fn test_scheduler_stop() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    engine.add(device(0, 40));
+
+    scheduler
+        .start(&timer, &mut engine.plan(), 10_000, 0)
+        .unwrap();
+    scheduler.stop(&timer).unwrap();
+
+    assert_eq!(timer.alarm(), None);
+
+    scheduler
+        .start(&timer, &mut engine.plan(), 10_000, 0)
+        .unwrap();
+
+    assert_eq!(timer.alarm(), None);
+    assert_eq!(scheduler.alarm(&timer, 0).unwrap(), None);
+}
+
+#[test]
+fn test_scheduler_sub_percent() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::new();
+
+    engine.add(device(0, 0));
+    engine.set_power_permille(0, 125).unwrap();
+
+    // Fire at 87.5%
+    let alarms = run(&mut engine, &mut scheduler, &timer, 10_000, 0);
+
+    assert_eq!(alarms, vec![(0, 0), (8_750, 875), (9_500, 950)]);
+}
+
+#[test]
+fn test_scheduler_reuse_plan_buffer() {
+    let tick = AtomicU8::new(0);
+    let mut engine = PhaseAngleEngine::new(&tick, 1, 95);
+    let timer = SimulatedTimer::new();
+    let mut scheduler = Scheduler::with_capacity(951);
+    let mut plan = Vec::with_capacity(951);
+
+    engine.add(device(0, 40));
+
+    for _ in 0..3 {
+        engine.plan_into(&mut plan);
+        scheduler.start(&timer, &mut plan, 10_000, 0).unwrap();
+
+        // Previous plan comes back, buffer is never reallocated
+        assert_eq!(plan.capacity(), 951);
+        assert_eq!(scheduler.next_tick(), Some(0));
+    }
+
+    engine.plan_into(&mut plan);
+
+    assert_eq!(plan, engine.plan());
+}
@@ -0,0 +1,135 @@
+//! Mains quality statistics
+//!
+//! Statistics are computed from timestamps (in µs) of accepted zero crossings. A half
+//! period longer than 1.5 expected half period means zero crossings were missed: it's
+//! counted as missed crossings and not used for min, max, average and jitter.
+//!
+//! Jitter is smoothed difference between two successive half periods (like RFC 3550).
+
+#[cfg(test)]
+mod tests;
+
+// Jitter smoothing factor (1/16)
+const JITTER_SMOOTHING: i64 = 16;
+
+/// Snapshot of mains statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MainsStatistics {
+    /// Frequency in Hz from average half period. `None` if not measured yet.
+    pub frequency: Option<f32>,
+    /// Shortest half period in µs.
+    pub min_half_period: Option<u32>,
+    /// Longest half period in µs.
+    pub max_half_period: Option<u32>,
+    /// Average half period in µs.
+    pub average_half_period: Option<u32>,
+    /// Smoothed variation between successive half periods in µs.
+    pub jitter: u32,
+    /// Zero crossings not detected.
+    pub missed_crossings: u32,
+    /// Edges rejected by glitch filter.
+    pub rejected_crossings: u32,
+    /// Half sinusoidal since first zero crossing (detected and missed).
+    pub half_cycles: u64,
+}
+
+/// Compute mains statistics from zero crossing timestamps.
+#[derive(Debug, Clone)]
+pub struct MainsStats {
+    // Expected half period in µs
+    half_period: u32,
+    // Timestamp of last zero crossing
+    last_crossing: Option<u64>,
+    // Last measured half period
+    last_half_period: Option<u32>,
+    // Sum and number of measured half periods
+    sum: u64,
+    measured: u64,
+    min: Option<u32>,
+    max: Option<u32>,
+    // Jitter in 1/16 µs
+    jitter: i64,
+    missed: u32,
+    half_cycles: u64,
+}
+
+impl MainsStats {
+    /// Create statistics for expected `half_period` (µs).
+    pub fn new(half_period: u32) -> Self {
+        Self {
+            half_period,
+            last_crossing: None,
+            last_half_period: None,
+            sum: 0,
+            measured: 0,
+            min: None,
+            max: None,
+            jitter: 0,
+            missed: 0,
+            half_cycles: 0,
+        }
+    }
+
+    /// Update expected half period, for example tracked by PLL.
+    pub fn set_half_period(&mut self, half_period: u32) {
+        self.half_period = half_period;
+    }
+
+    /// Zero crossing accepted at `timestamp`.
+    pub fn zero_crossing(&mut self, timestamp: u64) {
+        let last_crossing = self.last_crossing.replace(timestamp);
+
+        let last_crossing = match last_crossing {
+            Some(l) => l,
+            None => return,
+        };
+
+        let interval = timestamp.saturating_sub(last_crossing);
+
+        if self.half_period > 0 && interval * 2 > self.half_period as u64 * 3 {
+            // Rounded number of half periods
+            let periods = (interval + self.half_period as u64 / 2) / self.half_period as u64;
+
+            self.missed = self.missed.saturating_add(periods as u32 - 1);
+            self.half_cycles += periods;
+            // Next jitter is not comparable
+            self.last_half_period = None;
+
+            return;
+        }
+
+        let interval = interval as u32;
+
+        self.half_cycles += 1;
+        self.sum += interval as u64;
+        self.measured += 1;
+        self.min = Some(self.min.map_or(interval, |m| m.min(interval)));
+        self.max = Some(self.max.map_or(interval, |m| m.max(interval)));
+
+        if let Some(last) = self.last_half_period {
+            let d = (interval as i64 - last as i64).abs() * JITTER_SMOOTHING;
+
+            self.jitter += (d - self.jitter) / JITTER_SMOOTHING;
+        }
+
+        self.last_half_period = Some(interval);
+    }
+
+    /// Snapshot of statistics. Rejected crossings are counted by glitch filter.
+    pub fn statistics(&self) -> MainsStatistics {
+        let average_half_period = self.sum.checked_div(self.measured).map(|a| a as u32);
+
+        MainsStatistics {
+            frequency: average_half_period
+                .filter(|h| *h > 0)
+                .map(|h| 1_000_000.0 / (2.0 * h as f32)),
+            min_half_period: self.min,
+            max_half_period: self.max,
+            average_half_period,
+            jitter: (self.jitter / JITTER_SMOOTHING) as u32,
+            missed_crossings: self.missed,
+            rejected_crossings: 0,
+            half_cycles: self.half_cycles,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::stats::*;
+
+#[test]
+fn test_stats_empty() {
+    let mut stats = MainsStats::new(10_000);
+
+    assert_eq!(stats.statistics(), MainsStatistics::default());
+
+    // First zero crossing doesn't measure anything
+    stats.zero_crossing(1_000);
+
+    assert_eq!(stats.statistics(), MainsStatistics::default());
+}
+
+#[test]
+fn test_stats_half_periods() {
+    let mut stats = MainsStats::new(10_000);
+    let mut timestamp = 0;
+
+    stats.zero_crossing(timestamp);
+
+    for h in [9_900, 10_100, 9_900, 10_100] {
+        timestamp += h;
+        stats.zero_crossing(timestamp);
+    }
+
+    let s = stats.statistics();
+
+    assert_eq!(s.min_half_period, Some(9_900));
+    assert_eq!(s.max_half_period, Some(10_100));
+    assert_eq!(s.average_half_period, Some(10_000));
+    assert_eq!(s.frequency, Some(50.0));
+    assert_eq!(s.half_cycles, 4);
+    assert_eq!(s.missed_crossings, 0);
+    // 3 differences of 200µs, smoothed
+    assert!(s.jitter > 20 && s.jitter < 200);
+}
+
+#[test]
+fn test_stats_stable_mains_no_jitter() {
+    let mut stats = MainsStats::new(8_333);
+
+    for i in 0..100 {
+        stats.zero_crossing(i * 8_333);
+    }
+
+    let s = stats.statistics();
+
+    assert_eq!(s.jitter, 0);
+    assert_eq!(s.half_cycles, 99);
+    assert_eq!(s.frequency.unwrap().round(), 60.0);
+}
+
+#[test]
+fn test_stats_missed_crossings() {
+    let mut stats = MainsStats::new(10_000);
+
+    stats.zero_crossing(0);
+    stats.zero_crossing(10_000);
+    // 2 crossings missed
+    stats.zero_crossing(40_100);
+    stats.zero_crossing(50_100);
+
+    let s = stats.statistics();
+
+    assert_eq!(s.missed_crossings, 2);
+    assert_eq!(s.half_cycles, 5);
+    // Long half period is not measured
+    assert_eq!(s.max_half_period, Some(10_000));
+    assert_eq!(s.jitter, 0);
+}
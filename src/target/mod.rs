@@ -0,0 +1,82 @@
+//! Power targets
+//!
+//! Engine is shared with ISR timer, a task cannot always use it. Each device has a
+//! `PowerTarget`: an atomic mailbox where any task (on any core) writes requested power.
+//! Device takes last request on next half sinusoidal, so a request is never lost.
+//!
+//! Request is checked with limits of device when it's written, so errors are returned
+//! to caller.
+use crate::error::*;
+use crate::limit::PowerLimits;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+#[cfg(test)]
+mod tests;
+
+// No power requested
+const NO_REQUEST: u32 = u32::MAX;
+
+/// Power requested for a device, applied on next half sinusoidal.
+#[derive(Debug)]
+pub struct PowerTarget {
+    // Id of device
+    id: u8,
+    // Requested power in per-mille or `NO_REQUEST`
+    request: AtomicU32,
+    // Limits of device, to check requests. Only tasks lock it.
+    limits: Mutex<PowerLimits>,
+}
+
+impl PowerTarget {
+    /// Create target without request.
+    pub fn new(id: u8, limits: PowerLimits) -> Self {
+        Self {
+            id,
+            request: AtomicU32::new(NO_REQUEST),
+            limits: Mutex::new(limits),
+        }
+    }
+
+    /// Id of device.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Update limits used to check next requests.
+    pub fn set_limits(&self, limits: PowerLimits) {
+        *self.limits.lock().unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+
+    /// Check power in per-mille with limits of device, without requesting it.
+    pub fn check(&self, power: u16) -> Result<(), RbdDimmerError> {
+        self.limits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .apply_permille(power)
+            .map(|_| ())
+    }
+
+    /// Request power in per-mille. Power is checked with limits of device. A new request
+    /// replaces previous one if it's not applied yet.
+    pub fn request(&self, power: u16) -> Result<(), RbdDimmerError> {
+        self.check(power)?;
+        self.request.store(power as u32, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Take last requested power in per-mille.
+    #[inline(always)]
+    pub fn take(&self) -> Option<u16> {
+        match self.request.swap(NO_REQUEST, Ordering::Acquire) {
+            NO_REQUEST => None,
+            p => Some(p as u16),
+        }
+    }
+
+    /// A request is waiting.
+    pub fn is_pending(&self) -> bool {
+        self.request.load(Ordering::Acquire) != NO_REQUEST
+    }
+}
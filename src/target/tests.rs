@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use std::thread;
+
+use crate::error::RbdDimmerErrorKind;
+use crate::limit::{LimitPolicy, PowerLimits};
+use crate::target::*;
+
+#[test]
+fn test_power_target_last_request() {
+    let target = PowerTarget::new(2, PowerLimits::default());
+
+    assert_eq!(target.id(), 2);
+    assert_eq!(target.take(), None);
+
+    target.request(100).unwrap();
+    target.request(250).unwrap();
+
+    assert!(target.is_pending());
+    assert_eq!(target.take(), Some(250));
+    assert_eq!(target.take(), None);
+    assert!(!target.is_pending());
+}
+
+#[test]
+fn test_power_target_limits() {
+    let limits = PowerLimits::new(10, 80)
+        .unwrap()
+        .with_policy(LimitPolicy::Reject);
+    let target = PowerTarget::new(0, limits);
+
+    match target.request(900) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::PowerOutOfRange),
+    }
+
+    assert_eq!(target.take(), None);
+
+    // Same check without request
+    assert!(target.check(900).is_err());
+    assert!(target.check(500).is_ok());
+    assert_eq!(target.take(), None);
+
+    // Clamp is done by device
+    let target = PowerTarget::new(0, PowerLimits::new(10, 80).unwrap());
+
+    target.request(900).unwrap();
+
+    assert_eq!(target.take(), Some(900));
+}
+
+#[test]
+fn test_power_target_other_thread() {
+    let target = Arc::new(PowerTarget::new(0, PowerLimits::default()));
+    let task_target = target.clone();
+
+    thread::spawn(move || task_target.request(420).unwrap())
+        .join()
+        .unwrap();
+
+    assert_eq!(target.take(), Some(420));
+}
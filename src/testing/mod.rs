@@ -0,0 +1,88 @@
+//! Fakes shared by the unit tests of each module.
+
+use std::sync::atomic::{AtomicU32, Ordering as aOrdering};
+
+use crate::error::*;
+use crate::pll::Clock;
+use crate::zc::OutputPin;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub(crate) enum PinState {
+    Low,
+    High,
+    Unknow,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Good pin, remember last state
+pub(crate) struct FakePin {
+    pub(crate) pin_state: PinState,
+}
+
+impl FakePin {
+    pub fn new() -> Self {
+        Self {
+            pin_state: PinState::Unknow,
+        }
+    }
+}
+
+impl OutputPin for FakePin {
+    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
+        self.pin_state = PinState::High;
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
+        self.pin_state = PinState::Low;
+        Ok(())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Fail pin, every call return an error
+pub(crate) struct FakeFailPin;
+
+impl FakeFailPin {
+    pub fn new() -> Self {
+        FakeFailPin {}
+    }
+}
+
+impl OutputPin for FakeFailPin {
+    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
+        Err(RbdDimmerError::from(RbdDimmerErrorKind::SetHigh))
+    }
+
+    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
+        Err(RbdDimmerError::from(RbdDimmerErrorKind::SetLow))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Clock moved by hand, in microseconds
+pub(crate) struct FakeClock {
+    now: AtomicU32,
+}
+
+impl FakeClock {
+    pub fn new(now: u32) -> Self {
+        Self {
+            now: AtomicU32::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u32) {
+        self.now.store(now, aOrdering::Relaxed);
+    }
+
+    pub fn advance(&self, us: u32) {
+        self.now.fetch_add(us, aOrdering::Relaxed);
+    }
+}
+
+impl Clock for &FakeClock {
+    fn now(&self) -> u32 {
+        self.now.load(aOrdering::Relaxed)
+    }
+}
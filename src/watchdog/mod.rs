@@ -0,0 +1,84 @@
+//! Zero crossing watchdog
+//!
+//! If zero crossing signal disappears (mains off, broken wire), no half sinusoidal starts
+//! and outputs keep their state. The watchdog is checked periodically: when no zero
+//! crossing happens during timeout, mains is lost. Mains is restored on next zero
+//! crossing.
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// Default time without zero crossing before mains is lost (5 periods in 50Hz).
+pub const DEFAULT_MAINS_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Change of mains state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MainsEvent {
+    /// No zero crossing during timeout.
+    Lost,
+    /// Zero crossing detected after mains was lost.
+    Restored,
+}
+
+/// Callback called when mains is lost or restored.
+pub type MainsCallback = Box<dyn FnMut(MainsEvent) + Send>;
+
+/// Detect loss of zero crossing signal. Timestamps are in µs.
+#[derive(Debug, Clone)]
+pub struct MainsWatchdog {
+    // Timeout in µs
+    timeout: u64,
+    // Timestamp of last zero crossing
+    last_crossing: Option<u64>,
+    // Mains is lost
+    lost: bool,
+}
+
+impl MainsWatchdog {
+    /// Create new watchdog. Watchdog starts at first zero crossing.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout: timeout.as_micros() as u64,
+            last_crossing: None,
+            lost: false,
+        }
+    }
+
+    /// Time without zero crossing before mains is lost.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_micros(self.timeout)
+    }
+
+    /// Zero crossing detected at `timestamp`. Return `MainsEvent::Restored` if mains was
+    /// lost.
+    pub fn zero_crossing(&mut self, timestamp: u64) -> Option<MainsEvent> {
+        self.last_crossing = Some(timestamp);
+
+        if self.lost {
+            self.lost = false;
+            Some(MainsEvent::Restored)
+        } else {
+            None
+        }
+    }
+
+    /// Check watchdog at `timestamp`. Return `MainsEvent::Lost` once when timeout is
+    /// reached.
+    pub fn check(&mut self, timestamp: u64) -> Option<MainsEvent> {
+        let last_crossing = self.last_crossing?;
+
+        if self.lost || timestamp.saturating_sub(last_crossing) < self.timeout {
+            return None;
+        }
+
+        self.lost = true;
+
+        Some(MainsEvent::Lost)
+    }
+
+    /// No zero crossing during timeout.
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use crate::watchdog::*;
+
+#[test]
+fn test_watchdog_not_started() {
+    let mut watchdog = MainsWatchdog::new(Duration::from_millis(100));
+
+    // No zero crossing yet
+    assert_eq!(watchdog.check(1_000_000), None);
+    assert!(!watchdog.is_lost());
+}
+
+#[test]
+fn test_watchdog_lost_then_restored() {
+    let mut watchdog = MainsWatchdog::new(Duration::from_millis(100));
+
+    assert_eq!(watchdog.timeout(), Duration::from_millis(100));
+
+    for i in 0..10 {
+        assert_eq!(watchdog.zero_crossing(i * 10_000), None);
+        assert_eq!(watchdog.check(i * 10_000 + 5_000), None);
+    }
+
+    // Last zero crossing at 90ms
+    assert_eq!(watchdog.check(189_999), None);
+    assert_eq!(watchdog.check(190_000), Some(MainsEvent::Lost));
+    assert!(watchdog.is_lost());
+
+    // Reported once
+    assert_eq!(watchdog.check(300_000), None);
+
+    assert_eq!(watchdog.zero_crossing(310_000), Some(MainsEvent::Restored));
+    assert!(!watchdog.is_lost());
+    assert_eq!(watchdog.zero_crossing(320_000), None);
+}
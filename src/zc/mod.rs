@@ -5,8 +5,16 @@
 //! This module not works like official library. Power is turn on/off on Zero Crossing event if device has MOC3021 triac to limit power-lost.
 //!
 use crate::error::*;
+use crate::fade::{self, Easing, Fade};
+use crate::fault::{FaultCallback, FaultPolicy, FaultState};
+use crate::frequency::Frequency;
+use crate::glitch::GlitchFilter;
+use crate::stats::{MainsStatistics, MainsStats};
+use std::future::Future;
 use std::sync::mpsc::{self, TryRecvError};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 mod tests;
@@ -20,6 +28,63 @@ pub struct DevicesDimmerManagerNotification {
     pub power: u8,
 }
 
+// Device added or removed by a controller, or power set by a handle. Applied by
+// manager before next zero crossing. Generation tells a device apart from a device
+// added later with same id.
+enum DeviceChange<O>
+where
+    O: OutputPin,
+{
+    Add(DimmerDevice<O>),
+    Remove(u8, Option<u32>),
+    Power(u8, u32, u8),
+}
+
+// Devices of a manager, shared with its controllers. Updated when a change is sent, so
+// errors are returned to sender.
+#[derive(Default)]
+struct Registry {
+    // Id and generation of devices, with devices not added yet
+    devices: Vec<(u8, u32)>,
+    // Generation of next device
+    generation: u32,
+}
+
+impl Registry {
+    // Register id. Return generation of device.
+    fn add(&mut self, id: u8) -> Result<u32, RbdDimmerError> {
+        if self.devices.iter().any(|(i, _)| *i == id) {
+            return Err(RbdDimmerError::new(
+                RbdDimmerErrorKind::DuplicateDeviceId,
+                format!("Device {} already exists", id),
+            ));
+        }
+
+        let generation = self.generation;
+
+        self.generation = self.generation.wrapping_add(1);
+        self.devices.push((id, generation));
+
+        Ok(generation)
+    }
+
+    // Device `id` is registered. With `generation`, device must be this one.
+    fn contains(&self, id: u8, generation: Option<u32>) -> bool {
+        self.devices
+            .iter()
+            .any(|(i, g)| *i == id && generation.is_none_or(|generation| *g == generation))
+    }
+
+    fn remove(&mut self, id: u8) {
+        self.devices.retain(|(i, _)| *i != id);
+    }
+}
+
+// Lock registry. A panic while locked cannot break it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 /// Abstract output pin
 pub trait OutputPin {
     /// Set the output as high
@@ -35,6 +100,29 @@ pub trait ZeroCrossingPin {
     fn wait_for_rising_edge(&mut self) -> Result<(), RbdDimmerError>;
 }
 
+/// Async zero crossing pin abstract. Implementation can use `async fn`.
+pub trait AsyncZeroCrossingPin {
+    /// Wait for rising without blocking executor
+    fn wait_for_rising_edge(&mut self) -> impl Future<Output = Result<(), RbdDimmerError>>;
+}
+
+/// Snapshot of state of a device
+#[derive(Debug, Clone)]
+pub struct DeviceState {
+    /// Id of device
+    pub id: u8,
+    /// Power requested. During a fade, target of fade.
+    pub requested_power: u8,
+    /// Current power. During a fade, current power of fade.
+    pub power: u8,
+    /// A fade is in progress
+    pub fading: bool,
+    /// Faults of output pin
+    pub fault: FaultState,
+    /// Device is turned on during some half sinusoidal
+    pub is_on: bool,
+}
+
 /// Struct to manage power of dimmer device
 pub struct DimmerDevice<O>
 where
@@ -43,6 +131,12 @@ where
     id: u8,
     pin: O,
     power: u8,
+    // Registration in manager
+    generation: u32,
+    // Fade in progress
+    fade: Option<Fade>,
+    // Errors of output pin
+    fault: FaultState,
 }
 
 impl<O> DimmerDevice<O>
@@ -51,22 +145,104 @@ where
 {
     /// Create new struct
     pub fn new(id: u8, pin: O) -> Self {
-        Self { id, pin, power: 0 }
+        Self {
+            id,
+            pin,
+            power: 0,
+            generation: 0,
+            fade: None,
+            fault: FaultState::new(),
+        }
     }
 
-    /// Set power of device. Power is percent
+    /// Set power of device. Power is percent. Fade in progress is cancelled.
     pub fn set_power(&mut self, p: u8) {
+        self.fade = None;
         self.power = p;
     }
 
-    /// Value of tick increase by zero crossing interrupt
+    /// Start a fade from current power to `target` in `half_cycles` half sinusoidal.
+    pub fn fade_to(&mut self, target: u8, half_cycles: u32, easing: Easing) {
+        self.fade = Some(Fade::new(self.power, target, half_cycles, easing));
+    }
+
+    /// Stop fade in progress. Device keep current power.
+    pub fn cancel_fade(&mut self) {
+        self.fade = None;
+    }
+
+    /// No fade in progress.
+    pub fn is_fade_done(&self) -> bool {
+        self.fade.is_none()
+    }
+
+    /// Current power. During a fade, current power of fade.
+    pub fn power(&self) -> u8 {
+        self.power
+    }
+
+    /// Snapshot of state of device.
+    pub fn state(&self) -> DeviceState {
+        DeviceState {
+            id: self.id,
+            requested_power: self.fade.as_ref().map_or(self.power, |f| f.target()),
+            power: self.power,
+            fading: self.fade.is_some(),
+            fault: self.fault.clone(),
+            is_on: self.power > 0 && !self.fault.is_disabled(),
+        }
+    }
+
+    /// Advance fade of one half sinusoidal.
+    pub fn update_fade(&mut self) {
+        if let Some(fade) = self.fade.as_mut() {
+            self.power = fade.step();
+
+            if fade.is_done() {
+                self.fade = None;
+            }
+        }
+    }
+
+    /// Value of tick increase by zero crossing interrupt. Errors are recorded in fault
+    /// state. Disabled device is not driven.
     pub fn tick(&mut self, t: u8) -> Result<(), RbdDimmerError> {
+        if self.fault.is_disabled() {
+            return Ok(());
+        }
+
         // If power percent is over, shutdown pin
-        if t > self.power {
-            return self.pin.set_low();
+        let result = if t > self.power {
+            self.pin.set_low()
+        } else {
+            self.pin.set_high()
+        };
+
+        if let Err(e) = &result {
+            self.fault.record(e.clone());
         }
 
-        self.pin.set_high()
+        result
+    }
+
+    /// Faults of output pin.
+    pub fn fault(&self) -> &FaultState {
+        &self.fault
+    }
+
+    /// Clear latched fault and drive device again.
+    pub fn clear_fault(&mut self) {
+        self.fault.clear();
+    }
+
+    /// Id of device.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Give back pin used to turn on/off.
+    pub fn into_pin(self) -> O {
+        self.pin
     }
 
     #[cfg(test)]
@@ -80,7 +256,6 @@ where
 pub struct DevicesDimmerManager<O, ZC>
 where
     O: OutputPin,
-    ZC: ZeroCrossingPin,
 {
     // Devices to manage
     devices: Vec<DimmerDevice<O>>,
@@ -89,56 +264,257 @@ where
     // Channel to communicate with thread
     tx_power_change: Sender<DevicesDimmerManagerNotification>,
     rx_power_change: Receiver<DevicesDimmerManagerNotification>,
+    // Devices added or removed by controllers
+    tx_device_change: Sender<DeviceChange<O>>,
+    rx_device_change: Receiver<DeviceChange<O>>,
+    // Devices, with devices sent by controllers and not added yet
+    registry: Arc<Mutex<Registry>>,
     // Current counter of zero crossing
     counter: u8,
+    // What to do when an output pin fails
+    fault_policy: FaultPolicy,
+    // All devices are stopped by a fault
+    stopped: bool,
+    // Called with new faults
+    on_fault: Option<FaultCallback>,
+    // Reject spikes on zero crossing pin
+    glitch_filter: Option<GlitchFilter>,
+    // Start of manager, to timestamp edges
+    start: Instant,
+    // Mains quality
+    stats: MainsStats,
 }
 
 impl<O, ZC> DevicesDimmerManager<O, ZC>
 where
     O: OutputPin,
-    ZC: ZeroCrossingPin,
 {
     pub fn new(zero_crossing_pin: ZC) -> Self {
         let (tx_power_change, rx_power_change): (
             Sender<DevicesDimmerManagerNotification>,
             Receiver<DevicesDimmerManagerNotification>,
         ) = mpsc::channel();
+        let (tx_device_change, rx_device_change) = mpsc::channel();
 
         Self {
             devices: vec![],
             zero_crossing_pin,
             tx_power_change,
             rx_power_change,
+            tx_device_change,
+            rx_device_change,
+            registry: Arc::new(Mutex::new(Registry::default())),
             counter: 1,
+            fault_policy: FaultPolicy::default(),
+            stopped: false,
+            on_fault: None,
+            glitch_filter: None,
+            start: Instant::now(),
+            stats: MainsStats::new(Frequency::F50HZ.half_period().unwrap_or_default()),
         }
     }
 
-    pub fn wait_zero_crossing(&mut self) -> Result<(), RbdDimmerError> {
-        if self.read_power_update_message().is_err() {
-            return Err(RbdDimmerError::from(
+    pub fn sender(&self) -> Sender<DevicesDimmerManagerNotification> {
+        self.tx_power_change.clone()
+    }
+
+    /// Controller to add and remove devices from other tasks, while manager waits zero
+    /// crossing.
+    pub fn controller(&self) -> DimmerController<O> {
+        DimmerController {
+            registry: self.registry.clone(),
+            tx_device_change: self.tx_device_change.clone(),
+        }
+    }
+
+    /// Add a device. Return `RbdDimmerErrorKind::DuplicateDeviceId` if a device has same
+    /// id.
+    pub fn add(&mut self, device: DimmerDevice<O>) -> Result<(), RbdDimmerError> {
+        self.add_device(device)
+    }
+
+    /// Add a device between two zero crossings. Return
+    /// `RbdDimmerErrorKind::DuplicateDeviceId` if a device has same id.
+    pub fn add_device(&mut self, mut device: DimmerDevice<O>) -> Result<(), RbdDimmerError> {
+        device.generation = lock(&self.registry).add(device.id)?;
+        self.devices.push(device);
+
+        Ok(())
+    }
+
+    /// Remove a device. Its pin is set low before device is returned. If pin fails,
+    /// device is kept.
+    pub fn remove_device(&mut self, id: u8) -> Result<DimmerDevice<O>, RbdDimmerError> {
+        let index = match self.devices.iter().position(|d| d.id == id) {
+            Some(i) => i,
+            None => return Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        };
+
+        self.devices[index].pin.set_low()?;
+
+        lock(&self.registry).remove(id);
+
+        Ok(self.devices.remove(index))
+    }
+
+    /// Start a fade of a device to `target` during `duration`.
+    pub fn fade_to(
+        &mut self,
+        id: u8,
+        target: u8,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), RbdDimmerError> {
+        // This module works only for 50Hz
+        let half_cycles =
+            fade::half_cycles(duration, Frequency::F50HZ.half_period().unwrap_or_default());
+
+        self.device_mut(id)?.fade_to(target, half_cycles, easing);
+
+        Ok(())
+    }
+
+    /// Stop fade of a device.
+    pub fn cancel_fade(&mut self, id: u8) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.cancel_fade();
+        Ok(())
+    }
+
+    /// Fade of a device is finished.
+    pub fn is_fade_done(&self, id: u8) -> Result<bool, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device.is_fade_done()),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Current power of a device.
+    pub fn get_power(&self, id: u8) -> Result<u8, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device.power()),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Snapshot of state of a device. Devices stopped by fault are off.
+    pub fn device_state(&self, id: u8) -> Result<DeviceState, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => {
+                let mut state = device.state();
+
+                state.is_on = state.is_on && !self.stopped;
+
+                Ok(state)
+            }
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Ignore edges rejected by glitch filter. By default, there is no filter.
+    pub fn set_glitch_filter(&mut self, filter: GlitchFilter) {
+        self.glitch_filter = Some(filter);
+    }
+
+    /// Number of edges rejected by glitch filter.
+    pub fn rejected_crossings(&self) -> u32 {
+        match self.glitch_filter.as_ref() {
+            Some(filter) => filter.rejected(),
+            None => 0,
+        }
+    }
+
+    /// Mains statistics measured on zero crossings.
+    pub fn statistics(&self) -> MainsStatistics {
+        MainsStatistics {
+            rejected_crossings: self.rejected_crossings(),
+            ..self.stats.statistics()
+        }
+    }
+
+    /// Set what to do when an output pin fails. Default is `FaultPolicy::Ignore`.
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+    }
+
+    /// Set callback called when a device faults.
+    pub fn set_fault_callback(&mut self, callback: FaultCallback) {
+        self.on_fault = Some(callback);
+    }
+
+    /// Faults of a device.
+    pub fn fault(&self, id: u8) -> Result<FaultState, RbdDimmerError> {
+        match self.devices.iter().find(|d| d.id == id) {
+            Some(device) => Ok(device.fault().clone()),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
+    }
+
+    /// Clear latched fault of a device. With `FaultPolicy::StopAll`, devices restart
+    /// when no fault is latched.
+    pub fn clear_fault(&mut self, id: u8) -> Result<(), RbdDimmerError> {
+        self.device_mut(id)?.clear_fault();
+
+        if !self.devices.iter().any(|d| d.fault.is_latched()) {
+            self.stopped = false;
+        }
+
+        Ok(())
+    }
+
+    /// All devices are stopped by a fault (`FaultPolicy::StopAll`).
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    // Apply device changes and power updates received by channels
+    fn read_power_updates(&mut self) -> Result<(), RbdDimmerError> {
+        self.read_device_changes();
+
+        match self.read_power_update_message() {
+            Ok(()) => Ok(()),
+            Err(_) => Err(RbdDimmerError::from(
                 RbdDimmerErrorKind::ChannelCommunicationDisconnected,
-            ));
+            )),
+        }
+    }
+
+    // Rising edge now. Check it with glitch filter and update statistics.
+    fn accept_edge(&mut self) -> bool {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        let accepted = match self.glitch_filter.as_mut() {
+            Some(filter) => filter.edge(timestamp, None),
+            None => true,
+        };
+
+        if accepted {
+            self.stats.zero_crossing(timestamp);
         }
 
-        let result = self.zero_crossing_pin.wait_for_rising_edge();
+        accepted
+    }
 
+    // New half sinusoidal: advance counter and fades, then drive devices
+    fn half_cycle(&mut self) {
         self.counter += 1;
 
         if self.counter > 100 {
             self.counter = 1;
         }
 
-        self.call_all_dimmer(self.counter);
-
-        result
-    }
+        for dimmer in self.devices.iter_mut() {
+            dimmer.update_fade();
+        }
 
-    pub fn sender(&self) -> Sender<DevicesDimmerManagerNotification> {
-        self.tx_power_change.clone()
+        self.call_all_dimmer(self.counter);
+        self.report_faults();
     }
 
-    pub fn add(&mut self, device: DimmerDevice<O>) {
-        self.devices.push(device);
+    // Find device by id
+    fn device_mut(&mut self, id: u8) -> Result<&mut DimmerDevice<O>, RbdDimmerError> {
+        match self.devices.iter_mut().find(|d| d.id == id) {
+            Some(device) => Ok(device),
+            None => Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound)),
+        }
     }
 
     // For each message in channel.
@@ -156,6 +532,34 @@ where
         Ok(())
     }
 
+    // Apply changes sent by controllers and handles. Manager keeps a sender, channel is
+    // never closed.
+    fn read_device_changes(&mut self) {
+        while let Ok(change) = self.rx_device_change.try_recv() {
+            match change {
+                DeviceChange::Add(device) => self.devices.push(device),
+                DeviceChange::Remove(id, generation) => {
+                    if let Some(index) = self.position(id, generation) {
+                        let _ = self.devices[index].pin.set_low();
+                        self.devices.remove(index);
+                    }
+                }
+                DeviceChange::Power(id, generation, power) => {
+                    if let Some(index) = self.position(id, Some(generation)) {
+                        self.devices[index].set_power(power);
+                    }
+                }
+            }
+        }
+    }
+
+    // Index of device `id`, of `generation` if it's set
+    fn position(&self, id: u8, generation: Option<u32>) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|d| d.id == id && generation.is_none_or(|g| d.generation == g))
+    }
+
     // Update one dimmer power
     fn update_dimmer_power(&mut self, data: DevicesDimmerManagerNotification) {
         match self.devices.iter_mut().find(|d| d.id == data.id) {
@@ -164,14 +568,265 @@ where
         }
     }
 
-    // Call all dimmer with tick
+    // Call all dimmer with tick and apply fault policy on error
     fn call_all_dimmer(&mut self, counter: u8) {
+        if self.stopped {
+            return;
+        }
+
+        let mut failed = false;
+
         for dimmer in self.devices.iter_mut() {
-            // TODO ignore error?
-            let _ = dimmer.tick(counter);
+            if dimmer.tick(counter).is_err() {
+                failed = true;
+
+                if self.fault_policy == FaultPolicy::DisableDevice {
+                    dimmer.fault.disable();
+                    let _ = dimmer.pin.set_low();
+                }
+            }
+        }
+
+        if failed && self.fault_policy == FaultPolicy::StopAll {
+            self.stopped = true;
+
+            for dimmer in self.devices.iter_mut() {
+                let _ = dimmer.pin.set_low();
+            }
+        }
+    }
+
+    // Send new faults to callback
+    fn report_faults(&mut self) {
+        if let Some(callback) = self.on_fault.as_mut() {
+            for dimmer in self.devices.iter_mut() {
+                if let Some(e) = dimmer.fault.take_report() {
+                    callback(dimmer.id, &e);
+                }
+            }
         }
     }
 
-    // TODO remove()?
     // TODO stop()?
 }
+
+impl<O, ZC> DevicesDimmerManager<O, ZC>
+where
+    O: OutputPin,
+    ZC: ZeroCrossingPin,
+{
+    pub fn wait_zero_crossing(&mut self) -> Result<(), RbdDimmerError> {
+        self.read_power_updates()?;
+
+        let result = self.wait_valid_edge();
+
+        self.half_cycle();
+
+        result
+    }
+
+    // Wait rising edge accepted by glitch filter
+    fn wait_valid_edge(&mut self) -> Result<(), RbdDimmerError> {
+        loop {
+            self.zero_crossing_pin.wait_for_rising_edge()?;
+
+            if self.accept_edge() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<O, ZC> DevicesDimmerManager<O, ZC>
+where
+    O: OutputPin,
+    ZC: AsyncZeroCrossingPin,
+{
+    /// Like `wait_zero_crossing()`, but executor can run other tasks while waiting.
+    pub async fn wait_zero_crossing_async(&mut self) -> Result<(), RbdDimmerError> {
+        self.read_power_updates()?;
+
+        let result = self.wait_valid_edge_async().await;
+
+        self.half_cycle();
+
+        result
+    }
+
+    /// Driver task: wait zero crossings until an error happens.
+    pub async fn run(&mut self) -> Result<(), RbdDimmerError> {
+        loop {
+            self.wait_zero_crossing_async().await?;
+        }
+    }
+
+    // Wait rising edge accepted by glitch filter
+    async fn wait_valid_edge_async(&mut self) -> Result<(), RbdDimmerError> {
+        loop {
+            self.zero_crossing_pin.wait_for_rising_edge().await?;
+
+            if self.accept_edge() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Add and remove devices of a manager from other tasks, while manager waits zero
+/// crossing (hot-configure from a web UI...). Changes are applied before next zero
+/// crossing. Clone it for each task.
+pub struct DimmerController<O>
+where
+    O: OutputPin,
+{
+    // Devices of manager, checked when a change is sent
+    registry: Arc<Mutex<Registry>>,
+    tx_device_change: Sender<DeviceChange<O>>,
+}
+
+impl<O> Clone for DimmerController<O>
+where
+    O: OutputPin,
+{
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            tx_device_change: self.tx_device_change.clone(),
+        }
+    }
+}
+
+impl<O> DimmerController<O>
+where
+    O: OutputPin,
+{
+    /// Add a device. Return `RbdDimmerErrorKind::DuplicateDeviceId` if a device has same
+    /// id, even if it's not added yet.
+    pub fn add_device(&self, device: DimmerDevice<O>) -> Result<(), RbdDimmerError> {
+        self.add(device).map(|_| ())
+    }
+
+    /// Add a device and return its handle. Device is removed when handle is dropped.
+    pub fn register(&self, device: DimmerDevice<O>) -> Result<DimmerHandle<O>, RbdDimmerError> {
+        let id = device.id;
+        let generation = self.add(device)?;
+
+        Ok(DimmerHandle {
+            id,
+            generation,
+            controller: self.clone(),
+            released: false,
+        })
+    }
+
+    /// Remove a device. Its pin is set low, then device is dropped. Use
+    /// `DevicesDimmerManager::remove_device()` to get device back.
+    pub fn remove_device(&self, id: u8) -> Result<(), RbdDimmerError> {
+        self.remove(id, None)
+    }
+
+    // Add device. Return its generation.
+    fn add(&self, mut device: DimmerDevice<O>) -> Result<u32, RbdDimmerError> {
+        // Registry stays locked while sending, so changes are received in same order
+        let mut registry = lock(&self.registry);
+        let id = device.id;
+
+        device.generation = registry.add(id)?;
+
+        let generation = device.generation;
+
+        if let Err(e) = self.send(DeviceChange::Add(device)) {
+            registry.remove(id);
+            return Err(e);
+        }
+
+        Ok(generation)
+    }
+
+    // Remove device, of `generation` if it's set
+    fn remove(&self, id: u8, generation: Option<u32>) -> Result<(), RbdDimmerError> {
+        let mut registry = lock(&self.registry);
+
+        if !registry.contains(id, generation) {
+            return Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound));
+        }
+
+        self.send(DeviceChange::Remove(id, generation))?;
+        registry.remove(id);
+
+        Ok(())
+    }
+
+    // Set power of device `id` if it's still the one of `generation`
+    fn set_power(&self, id: u8, generation: u32, power: u8) -> Result<(), RbdDimmerError> {
+        let registry = lock(&self.registry);
+
+        if !registry.contains(id, Some(generation)) {
+            return Err(RbdDimmerError::from(RbdDimmerErrorKind::DimmerNotFound));
+        }
+
+        self.send(DeviceChange::Power(id, generation, power))
+    }
+
+    // Error if manager is dropped
+    fn send(&self, change: DeviceChange<O>) -> Result<(), RbdDimmerError> {
+        match self.tx_device_change.send(change) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(RbdDimmerError::from(
+                RbdDimmerErrorKind::ChannelCommunicationDisconnected,
+            )),
+        }
+    }
+}
+
+/// Handle of a device added with `DimmerController::register()`. Device is turned off
+/// and removed when handle is dropped or released, so its id can be added again. If
+/// device is removed by id, handle doesn't control a new device with same id: it returns
+/// `RbdDimmerErrorKind::DimmerNotFound`.
+pub struct DimmerHandle<O>
+where
+    O: OutputPin,
+{
+    // Id of device
+    id: u8,
+    // Registration of device
+    generation: u32,
+    // Controller of manager of device
+    controller: DimmerController<O>,
+    // Device is already removed
+    released: bool,
+}
+
+impl<O> DimmerHandle<O>
+where
+    O: OutputPin,
+{
+    /// Id of device.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Set power of device in percent. Power is applied before next zero crossing, fade
+    /// in progress is cancelled.
+    pub fn set_power(&self, power: u8) -> Result<(), RbdDimmerError> {
+        self.controller.set_power(self.id, self.generation, power)
+    }
+
+    /// Turn off and remove device. Device is dropped after its pin is set low.
+    pub fn release(mut self) -> Result<(), RbdDimmerError> {
+        // Nothing to retry on drop
+        self.released = true;
+        self.controller.remove(self.id, Some(self.generation))
+    }
+}
+
+impl<O> Drop for DimmerHandle<O>
+where
+    O: OutputPin,
+{
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.controller.remove(self.id, Some(self.generation));
+        }
+    }
+}
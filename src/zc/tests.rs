@@ -1,61 +1,18 @@
+use std::future::{poll_fn, Future};
+use std::pin::pin;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
-
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Duration;
+
+use crate::fade::Easing;
+use crate::fault::FaultPolicy;
+use crate::glitch::GlitchFilter;
+use crate::testing::{FakeFailPin, FakePin, PinState};
 use crate::zc::*;
 
-#[derive(Debug, Clone, PartialEq, Copy)]
-enum PinState {
-    Low,
-    High,
-    Unknow,
-}
-
-struct FakePin {
-    pin_state: PinState,
-}
-
-///////////////////////////////////////////////////////////////////////////////
-// Good struct
-impl FakePin {
-    pub fn new() -> Self {
-        Self {
-            pin_state: PinState::Unknow,
-        }
-    }
-}
-
-impl OutputPin for FakePin {
-    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
-        self.pin_state = PinState::High;
-        Ok(())
-    }
-
-    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
-        self.pin_state = PinState::Low;
-        Ok(())
-    }
-}
-
-///////////////////////////////////////////////////////////////////////////////
-// Fail struct
-struct FakeFailPin;
-
-impl FakeFailPin {
-    pub fn new() -> Self {
-        FakeFailPin {}
-    }
-}
-
-impl OutputPin for FakeFailPin {
-    fn set_high(&mut self) -> Result<(), RbdDimmerError> {
-        Err(RbdDimmerError::from(RbdDimmerErrorKind::SetHigh))
-    }
-
-    fn set_low(&mut self) -> Result<(), RbdDimmerError> {
-        Err(RbdDimmerError::from(RbdDimmerErrorKind::SetLow))
-    }
-}
-
 #[test]
 fn test_dimmer_device_pin_up_then_low() {
     let fake_pin = FakePin::new();
@@ -94,6 +51,13 @@ fn test_dimmer_device_fail() {
         Ok(()) => panic!(),
         Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::SetLow),
     }
+
+    // First error is latched
+    assert_eq!(dim_device.fault().count(), 2);
+    assert_eq!(
+        dim_device.fault().error().unwrap().kind,
+        RbdDimmerErrorKind::SetHigh
+    );
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -128,7 +92,7 @@ fn test_devices_dimmer_manager_turn_device_up_then_down() {
         DevicesDimmerManager::new(zero_crossing_pin);
 
     // Add the device
-    devices_dimmer_manager.add(dim_device);
+    devices_dimmer_manager.add(dim_device).unwrap();
 
     // Set power to 10 of device 0
     let tx_power = devices_dimmer_manager.sender();
@@ -161,3 +125,490 @@ fn test_devices_dimmer_manager_turn_device_up_then_down() {
         PinState::Low
     );
 }
+
+#[test]
+fn test_devices_dimmer_manager_fade() {
+    let fake_pin = FakePin::new();
+
+    let dim_device = DimmerDevice::new(0, fake_pin);
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager.add(dim_device).unwrap();
+
+    // 100 half sinusoidal at 50Hz
+    devices_dimmer_manager
+        .fade_to(0, 100, Duration::from_secs(1), Easing::Linear)
+        .unwrap();
+
+    assert!(!devices_dimmer_manager.is_fade_done(0).unwrap());
+
+    // Counter 2, power 1
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::Low
+    );
+
+    // Counter 3, power 2
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::Low
+    );
+
+    for _ in 0..98 {
+        zc_sender.send(true).unwrap();
+        devices_dimmer_manager.wait_zero_crossing().unwrap();
+    }
+
+    assert!(devices_dimmer_manager.is_fade_done(0).unwrap());
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::High
+    );
+}
+
+#[test]
+fn test_devices_dimmer_manager_cancel_fade() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager
+        .add(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+
+    devices_dimmer_manager
+        .fade_to(0, 100, Duration::from_secs(1), Easing::Linear)
+        .unwrap();
+
+    for _ in 0..10 {
+        zc_sender.send(true).unwrap();
+        devices_dimmer_manager.wait_zero_crossing().unwrap();
+    }
+
+    devices_dimmer_manager.cancel_fade(0).unwrap();
+
+    assert!(devices_dimmer_manager.is_fade_done(0).unwrap());
+
+    // Power stay 10%
+    for _ in 0..10 {
+        zc_sender.send(true).unwrap();
+        devices_dimmer_manager.wait_zero_crossing().unwrap();
+    }
+
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::Low
+    );
+
+    match devices_dimmer_manager.cancel_fade(1) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_devices_dimmer_manager_fault() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakeFailPin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+    let faults = Arc::new(Mutex::new(vec![]));
+    let callback_faults = faults.clone();
+
+    devices_dimmer_manager
+        .add(DimmerDevice::new(0, FakeFailPin::new()))
+        .unwrap();
+    devices_dimmer_manager.set_fault_policy(FaultPolicy::DisableDevice);
+    devices_dimmer_manager.set_fault_callback(Box::new(move |id, e| {
+        callback_faults.lock().unwrap().push((id, e.kind.clone()));
+    }));
+
+    for _ in 0..3 {
+        zc_sender.send(true).unwrap();
+
+        // Fault doesn't stop manager
+        assert!(devices_dimmer_manager.wait_zero_crossing().is_ok());
+    }
+
+    let fault = devices_dimmer_manager.fault(0).unwrap();
+
+    assert_eq!(fault.count(), 1);
+    assert!(fault.is_disabled());
+    assert_eq!(
+        *faults.lock().unwrap(),
+        vec![(0, RbdDimmerErrorKind::SetLow)]
+    );
+
+    devices_dimmer_manager.clear_fault(0).unwrap();
+
+    assert!(!devices_dimmer_manager.fault(0).unwrap().is_latched());
+}
+
+#[test]
+fn test_devices_dimmer_manager_glitch_filter() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager
+        .add(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+    // Edges are accepted after 1.5ms
+    devices_dimmer_manager.set_glitch_filter(GlitchFilter::new(2_000));
+
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(devices_dimmer_manager.counter, 2);
+
+    // Spike then zero crossing
+    zc_sender.send(true).unwrap();
+
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(3));
+        zc_sender.send(true).unwrap();
+    });
+
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+    handle.join().unwrap();
+
+    assert_eq!(devices_dimmer_manager.counter, 3);
+    assert_eq!(devices_dimmer_manager.rejected_crossings(), 1);
+
+    let statistics = devices_dimmer_manager.statistics();
+
+    assert_eq!(statistics.rejected_crossings, 1);
+    assert_eq!(statistics.half_cycles, 1);
+    assert!(statistics.min_half_period.unwrap() >= 1_500);
+}
+
+#[test]
+fn test_devices_dimmer_manager_remove_device() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager
+        .add_device(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+    devices_dimmer_manager
+        .add_device(DimmerDevice::new(1, FakePin::new()))
+        .unwrap();
+
+    match devices_dimmer_manager.add_device(DimmerDevice::new(1, FakePin::new())) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DuplicateDeviceId),
+    }
+
+    match devices_dimmer_manager.add(DimmerDevice::new(0, FakePin::new())) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DuplicateDeviceId),
+    }
+
+    let tx_power = devices_dimmer_manager.sender();
+
+    tx_power
+        .send(DevicesDimmerManagerNotification { id: 1, power: 10 })
+        .unwrap();
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(
+        devices_dimmer_manager.devices[1].pin().pin_state,
+        PinState::High
+    );
+
+    let device = devices_dimmer_manager.remove_device(1).unwrap();
+
+    assert_eq!(device.id(), 1);
+    assert_eq!(device.into_pin().pin_state, PinState::Low);
+    assert_eq!(devices_dimmer_manager.devices.len(), 1);
+
+    match devices_dimmer_manager.remove_device(1) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+#[test]
+fn test_devices_dimmer_manager_controller() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+    let controller = devices_dimmer_manager.controller();
+
+    devices_dimmer_manager
+        .add_device(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+
+    // Manager waits zero crossing in its thread
+    let waiting = thread::spawn(move || {
+        devices_dimmer_manager.wait_zero_crossing().unwrap();
+        devices_dimmer_manager
+    });
+
+    controller
+        .add_device(DimmerDevice::new(1, FakePin::new()))
+        .unwrap();
+
+    match controller
+        .clone()
+        .add_device(DimmerDevice::new(1, FakePin::new()))
+    {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DuplicateDeviceId),
+    }
+
+    controller.remove_device(0).unwrap();
+
+    match controller.remove_device(0) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+
+    zc_sender.send(true).unwrap();
+
+    let mut devices_dimmer_manager = waiting.join().unwrap();
+
+    // Changes are applied before next zero crossing
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(devices_dimmer_manager.devices.len(), 1);
+    assert_eq!(devices_dimmer_manager.devices[0].id(), 1);
+
+    // Manager and controllers share ids
+    match devices_dimmer_manager.add_device(DimmerDevice::new(1, FakePin::new())) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DuplicateDeviceId),
+    }
+
+    devices_dimmer_manager.remove_device(1).unwrap();
+    controller
+        .add_device(DimmerDevice::new(1, FakePin::new()))
+        .unwrap();
+}
+
+#[test]
+fn test_devices_dimmer_manager_handle() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+    let controller = devices_dimmer_manager.controller();
+
+    let lamp = controller
+        .register(DimmerDevice::new(2, FakePin::new()))
+        .unwrap();
+
+    assert_eq!(lamp.id(), 2);
+
+    lamp.set_power(10).unwrap();
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(devices_dimmer_manager.get_power(2).unwrap(), 10);
+
+    // A new device with same id is not controlled by handle
+    controller.remove_device(2).unwrap();
+    controller
+        .add_device(DimmerDevice::new(2, FakePin::new()))
+        .unwrap();
+
+    match lamp.set_power(50) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+
+    // Dropped handle doesn't remove new device
+    drop(lamp);
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(devices_dimmer_manager.get_power(2).unwrap(), 0);
+
+    // Dropped handle removes its device, id can be added again
+    let fan = controller
+        .register(DimmerDevice::new(3, FakePin::new()))
+        .unwrap();
+
+    drop(fan);
+    controller
+        .add_device(DimmerDevice::new(3, FakePin::new()))
+        .unwrap();
+
+    let heater = controller
+        .register(DimmerDevice::new(4, FakePin::new()))
+        .unwrap();
+
+    heater.release().unwrap();
+    zc_sender.send(true).unwrap();
+    devices_dimmer_manager.wait_zero_crossing().unwrap();
+
+    assert_eq!(devices_dimmer_manager.devices.len(), 2);
+    assert_eq!(devices_dimmer_manager.devices[1].id(), 3);
+}
+
+#[test]
+fn test_devices_dimmer_manager_device_state() {
+    let zero_crossing_pin = FakeZeroCrossPin::new();
+    let zc_sender = zero_crossing_pin.tx_zc.clone();
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager
+        .add(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+
+    let state = devices_dimmer_manager.device_state(0).unwrap();
+
+    assert_eq!(state.power, 0);
+    assert!(!state.is_on);
+
+    // 100 half sinusoidal at 50Hz
+    devices_dimmer_manager
+        .fade_to(0, 100, Duration::from_secs(1), Easing::Linear)
+        .unwrap();
+
+    for _ in 0..10 {
+        zc_sender.send(true).unwrap();
+        devices_dimmer_manager.wait_zero_crossing().unwrap();
+    }
+
+    let state = devices_dimmer_manager.device_state(0).unwrap();
+
+    assert_eq!(devices_dimmer_manager.get_power(0).unwrap(), 10);
+    assert_eq!(state.id, 0);
+    assert_eq!(state.requested_power, 100);
+    assert_eq!(state.power, 10);
+    assert!(state.fading);
+    assert!(state.is_on);
+
+    match devices_dimmer_manager.get_power(1) {
+        Ok(_) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::DimmerNotFound),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Test async manager
+struct FakeAsyncZeroCrossPin {
+    // Zero crossings before pin fails
+    edges: u32,
+    // Next wait is pending once
+    pending: bool,
+}
+
+impl AsyncZeroCrossingPin for FakeAsyncZeroCrossPin {
+    async fn wait_for_rising_edge(&mut self) -> Result<(), RbdDimmerError> {
+        // Let executor run other tasks
+        poll_fn(|cx| {
+            self.pending = !self.pending;
+
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            Poll::Ready(())
+        })
+        .await;
+
+        if self.edges == 0 {
+            return Err(RbdDimmerError::from(RbdDimmerErrorKind::Other));
+        }
+
+        self.edges -= 1;
+
+        Ok(())
+    }
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Minimal executor for tests
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(r) => return r,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn test_devices_dimmer_manager_wait_zero_crossing_async() {
+    let zero_crossing_pin = FakeAsyncZeroCrossPin {
+        edges: 20,
+        pending: false,
+    };
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeAsyncZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    devices_dimmer_manager
+        .add(DimmerDevice::new(0, FakePin::new()))
+        .unwrap();
+    devices_dimmer_manager
+        .sender()
+        .send(DevicesDimmerManagerNotification { id: 0, power: 10 })
+        .unwrap();
+
+    block_on(devices_dimmer_manager.wait_zero_crossing_async()).unwrap();
+
+    assert_eq!(devices_dimmer_manager.get_power(0).unwrap(), 10);
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::High
+    );
+
+    for _ in 1..11 {
+        block_on(devices_dimmer_manager.wait_zero_crossing_async()).unwrap();
+    }
+
+    assert_eq!(
+        devices_dimmer_manager.devices[0].pin().pin_state,
+        PinState::Low
+    );
+    assert_eq!(devices_dimmer_manager.statistics().half_cycles, 10);
+}
+
+#[test]
+fn test_devices_dimmer_manager_run() {
+    let zero_crossing_pin = FakeAsyncZeroCrossPin {
+        edges: 5,
+        pending: false,
+    };
+    let mut devices_dimmer_manager: DevicesDimmerManager<FakePin, FakeAsyncZeroCrossPin> =
+        DevicesDimmerManager::new(zero_crossing_pin);
+
+    // Driver task ends when pin fails
+    match block_on(devices_dimmer_manager.run()) {
+        Ok(()) => panic!(),
+        Err(e) => assert_eq!(e.kind, RbdDimmerErrorKind::Other),
+    }
+
+    assert_eq!(devices_dimmer_manager.statistics().half_cycles, 4);
+}